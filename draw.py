@@ -3,7 +3,7 @@ import matplotlib.pyplot as plt
 import math
 # 读取 CSV 文件
 file_path = "memory_data.csv"
-data = pd.read_csv(file_path, header=None, names=["Time (s)", "Available Memory (GB)"])
+data = pd.read_csv(file_path, header=None, names=["Time (s)", "Available Memory (GB)", "Unreclaimed (B)"])
 
 # 绘制折线图
 plt.figure(figsize=(11, 6))
@@ -20,6 +20,18 @@ plt.legend(fontsize=18)
 # 保存图表为 PDF
 plt.savefig("memory_usage_plot.pdf")
 
+# Garbage held back by the tracker (retired but not yet reclaimed)
+if data["Unreclaimed (B)"].notna().any():
+    plt.figure(figsize=(11, 6))
+    plt.plot(data["Time (s)"]/10, data["Unreclaimed (B)"]/1024, marker=None, linestyle='-', color='r')
+    plt.xticks(fontsize=18)
+    plt.yticks(fontsize=18)
+    plt.title("Unreclaimed Memory Over Time", fontsize=26)
+    plt.xlabel("Time (seconds)", fontsize=24)
+    plt.ylabel("Unreclaimed Memory (KB)", fontsize=24)
+    plt.grid(True)
+    plt.savefig("unreclaimed_plot.pdf")
+
 throughput = [42208986.33,64138097.33,124661444.3,187069166.7,255669416.7,311593389,372864583.7,435501055.7,497961666.3]
 threads = [2,4,8,12,16,20,24,28,32]
 modified_throughput = []
@@ -0,0 +1,78 @@
+/* C interface to the reclamation schemes and the hash map, exported by the
+ * cdylib (libproject.so). Tracker names are those of the harness: "Hyaline",
+ * "Hyaline1", "Interval" and "Hazard". Every handle is used with the tracker
+ * or map that created it. A detected misuse aborts the process. */
+#ifndef SMR_H
+#define SMR_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SmrTracker SmrTracker;
+typedef struct SmrThread SmrThread;
+typedef struct SmrObject SmrObject;
+typedef struct SmrMap SmrMap;
+
+/* Reservation slots of a thread, valid slots for smr_protect are below this */
+#define SMR_SLOTS 3
+
+/* Called once a retired object can no longer be reached, on any thread */
+typedef void (*SmrDestructor)(void *ptr, void *ctx);
+
+/* NULL for an unknown name or a scheme the library was built without. Destroy
+ * only once every thread has unregistered, the destructors of all objects
+ * still retired run then. */
+SmrTracker *smr_tracker_create(const char *name);
+void smr_tracker_destroy(SmrTracker *tracker);
+
+/* One handle per thread, used by that thread only */
+SmrThread *smr_thread_register(SmrTracker *tracker);
+void smr_thread_unregister(SmrTracker *tracker, SmrThread *thread);
+
+/* An operation on the shared structure runs between pin and unpin. Objects
+ * loaded with smr_protect stay valid until unpin. */
+void smr_pin(SmrTracker *tracker, SmrThread *thread);
+void smr_unpin(SmrTracker *tracker, SmrThread *thread);
+
+/* Wrap `ptr` in an object of the tracker. Shared structures link objects,
+ * which every scheme can protect, and reach their data with smr_object_ptr. */
+SmrObject *smr_object_new(SmrTracker *tracker, SmrThread *thread, void *ptr);
+void *smr_object_ptr(const SmrObject *object);
+/* Free an object that was never published, without touching its pointer */
+void smr_object_free(SmrTracker *tracker, SmrObject *object);
+
+/* Load the object at `location` (an atomic pointer) and protect it in `slot`,
+ * while pinned */
+SmrObject *smr_protect(SmrTracker *tracker, SmrThread *thread, SmrObject *const *location, size_t slot);
+
+/* Hand over an object already unlinked from every shared location. Once no
+ * thread can hold it, `destructor(ptr, ctx)` runs and the object is freed. */
+void smr_retire(SmrTracker *tracker, SmrThread *thread, SmrObject *object, SmrDestructor destructor, void *ctx);
+
+/* Hash map of uint64_t to uint64_t whose nodes are reclaimed by the named
+ * tracker. NULL for an unknown name or no buckets. Only exported when the
+ * library is built with the "unordered-map" feature (on by default). */
+SmrMap *smr_map_create(const char *tracker_name, size_t bucket_count);
+void smr_map_destroy(SmrMap *map);
+
+SmrThread *smr_map_register(SmrMap *map);
+void smr_map_unregister(SmrMap *map, SmrThread *thread);
+
+/* false if the key is already present */
+bool smr_map_insert(SmrMap *map, SmrThread *thread, uint64_t key, uint64_t value);
+/* true if found, the value is stored through `value` unless it is NULL */
+bool smr_map_get(SmrMap *map, SmrThread *thread, uint64_t key, uint64_t *value);
+/* 1 if removed (value stored as for get), 0 if absent, -1 if rejected by the
+ * memory budget */
+int smr_map_remove(SmrMap *map, SmrThread *thread, uint64_t key, uint64_t *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
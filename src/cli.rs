@@ -0,0 +1,140 @@
+use clap::{value_parser, Arg, Command};
+#[cfg(feature = "hyaline")]
+use project::wide_head_lock_free;
+use project::{AllocatorType, BackoffStrategy, BudgetPolicy, ConcurrentMap, HasherType, MemoryBudget,
+              SortedUnorderedMap, TrackerConfig, TrackerType};
+#[cfg(feature = "split-ordered-map")]
+use project::SplitOrderedMap;
+use crate::run_test::GlobalTest;
+
+// Epoch frequency of Interval, fixed or tuned while the test runs
+#[derive(Clone, Copy)]
+enum EpochFreq {
+    Fixed(usize),
+    Adaptive,
+}
+
+fn parse_epoch_freq(arg: &str) -> Result<EpochFreq, String> {
+    match arg {
+        "adaptive" => Ok(EpochFreq::Adaptive),
+        _ => arg.parse().map(EpochFreq::Fixed).map_err(|_| "expected a number or adaptive".to_string()),
+    }
+}
+
+// Benchmark harness, `args` as on the command line with the program name first.
+// The run parameters are positional, in this order; later ones may be left out.
+pub(crate) fn run(args: Vec<String>) {
+    let maps = ["buckets", #[cfg(feature = "split-ordered-map")] "split"];
+    let defaults = TrackerConfig::default();
+    let matches = Command::new("project")
+        .about("Throughput and memory use of a concurrent map under a reclamation scheme")
+        .arg(Arg::new("threads").index(1).required(true).value_parser(value_parser!(i32).range(1..))
+            .help("Number of threads"))
+        .arg(Arg::new("read").index(2).required(true).value_parser(value_parser!(f64))
+            .help("Share of read operations, between 0 and 1"))
+        .arg(Arg::new("batch").index(3).default_value("1").value_parser(value_parser!(usize))
+            .help("Nodes a thread retires before its batch is inserted into the retirement list"))
+        .arg(Arg::new("budget").index(4).default_value("0").value_parser(value_parser!(u64))
+            .help("Cap on unreclaimed bytes, 0 for none"))
+        .arg(Arg::new("policy").index(5).default_value("block").value_parser(["block", "spin", "error"])
+            .help("What a thread does when the cap is reached"))
+        .arg(Arg::new("tracker").index(6)
+            .value_parser(|name: &str| TrackerType::lookup(name).map_err(|err| err.to_string()))
+            .help("Reclamation scheme, Hyaline, Hyaline1, Interval or Hazard, of those compiled in"))
+        .arg(Arg::new("epoch").index(7).value_parser(parse_epoch_freq)
+            .help(format!("Interval: allocations per thread between epochs (default {}), or adaptive to tune \
+                           both frequencies at runtime", defaults.epoch_freq)))
+        .arg(Arg::new("empty").index(8).value_parser(value_parser!(usize))
+            .help(format!("Interval and Hazard: retires between scans of a thread's list (default {})",
+                          defaults.empty_freq)))
+        .arg(Arg::new("allocator").index(9).value_parser(["system", "pool", "arena"])
+            .help("Where node blocks come from: system, pool (per-thread free lists) or arena (mmap slabs \
+                   returned to the OS)"))
+        .arg(Arg::new("backoff").index(10)
+            .value_parser(|name: &str| BackoffStrategy::from_name(name).ok_or("expected none, exp or yield"))
+            .help("Backoff after a failed CAS: none, exp[:max spins] or yield[:failures before yielding]"))
+        .arg(Arg::new("quarantine").index(11).default_value("0").value_parser(value_parser!(usize))
+            .help("Use-after-free mode: freed nodes are poisoned and held in a quarantine of this many blocks"))
+        .arg(Arg::new("check").index(12).value_parser(["check", "nocheck"])
+            .help("check catches double retires and, with one thread, retires of nodes that are still linked \
+                   (default nocheck)"))
+        .arg(Arg::new("export").index(13)
+            .help("Path prefix, the map and tracker state is written to <prefix>.dot and <prefix>.json \
+                   (\"\" for none)"))
+        .arg(Arg::new("buckets").long("buckets").default_value("1").value_parser(value_parser!(u64).range(1..))
+            .help("Buckets of the map, 1 makes it a single long list"))
+        .arg(Arg::new("hasher").long("hasher").default_value("siphash").value_parser(["siphash", "mult", "identity"])
+            .help("Hash of a key: siphash, mult (multiplicative) or identity (integer keys only)"))
+        .arg(Arg::new("map").long("map").default_value("buckets").value_parser(maps)
+            .help("The map: buckets (a fixed table of sorted lists) or split (split-ordered, the table starts \
+                   at the bucket count and grows with the keys)"))
+        .get_matches_from(args);
+
+    let num_threads = *matches.get_one::<i32>("threads").unwrap();
+    let percentage = *matches.get_one::<f64>("read").unwrap();
+    println!("Number of Threads: {}", num_threads);
+    println!("Percentage of reading: {}", percentage);
+    let batch_size = *matches.get_one::<usize>("batch").unwrap();
+    println!("Batch size: {}", batch_size);
+    let max_bytes = *matches.get_one::<u64>("budget").unwrap();
+    let budget = (max_bytes > 0).then(|| {
+        let policy = BudgetPolicy::from_name(matches.get_one::<String>("policy").unwrap()).unwrap();
+        println!("Memory budget: {} bytes ({:?})", max_bytes, policy);
+        MemoryBudget::new(max_bytes, policy)
+    });
+    let tracker_type = matches.get_one::<TrackerType>("tracker").copied()
+        .unwrap_or_else(|| TrackerType::enabled().next().unwrap());
+    println!("Tracker: {:?}", tracker_type);
+    // Without a native 128-bit CAS the Hyaline head is guarded by a lock
+    #[cfg(feature = "hyaline")]
+    {
+        let lock_free = wide_head_lock_free();
+        println!("128-bit CAS: {}", if lock_free { "lock-free" } else { "lock-based" });
+        if !lock_free && tracker_type == TrackerType::Hyaline {
+            eprintln!("Warning: the Hyaline head falls back to a lock on this target, Hyaline1 only needs a 64-bit CAS");
+        }
+    }
+    let mut config = TrackerConfig { batch_size, budget, ..defaults };
+    match matches.get_one::<EpochFreq>("epoch") {
+        Some(EpochFreq::Adaptive) => config.adaptive = true,
+        Some(EpochFreq::Fixed(epoch_freq)) => config.epoch_freq = *epoch_freq,
+        None => {}
+    }
+    if let Some(empty_freq) = matches.get_one::<usize>("empty") {
+        config.empty_freq = *empty_freq;
+    }
+    if let Some(name) = matches.get_one::<String>("allocator") {
+        config.allocator = AllocatorType::from_name(name).unwrap();
+        println!("Allocator: {}", name);
+    }
+    if let Some(backoff) = matches.get_one::<BackoffStrategy>("backoff") {
+        config.backoff = *backoff;
+        println!("Backoff: {}", config.backoff);
+    }
+    config.quarantine = *matches.get_one::<usize>("quarantine").unwrap();
+    if config.quarantine > 0 {
+        println!("Use-after-free checks: quarantine of {} blocks", config.quarantine);
+    }
+    if let Some(check) = matches.get_one::<String>("check") {
+        config.check_retire = check == "check";
+        println!("Retire checks: {}", config.check_retire);
+    }
+    let export = matches.get_one::<String>("export").filter(|prefix| !prefix.is_empty()).cloned();
+    let bucket_count = *matches.get_one::<u64>("buckets").unwrap() as usize;
+    let hasher = HasherType::from_name(matches.get_one::<String>("hasher").unwrap()).unwrap();
+    let tracker_name = format!("{:?}", tracker_type);
+    let list: Box<dyn ConcurrentMap<i64, i32>> = match matches.get_one::<String>("map").unwrap().as_str() {
+        "buckets" => {
+            println!("Buckets: {} ({} hash)", bucket_count, hasher);
+            Box::new(SortedUnorderedMap::with_hasher(bucket_count, hasher, &tracker_name, config))
+        }
+        #[cfg(feature = "split-ordered-map")]
+        "split" => {
+            println!("Buckets: {} initially, split-ordered ({} hash)", bucket_count.next_power_of_two(), hasher);
+            Box::new(SplitOrderedMap::with_hasher(bucket_count, hasher, &tracker_name, config))
+        }
+        _ => unreachable!(),
+    };
+    let test = GlobalTest::new(32, num_threads, percentage, list, export, max_bytes > 0);
+    test.run_test();
+}
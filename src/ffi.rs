@@ -0,0 +1,209 @@
+// C interface to the trackers and the map, declared in include/smr.h. Every
+// function takes the handles the create and register calls returned; the
+// contract of each one, including what the caller must guarantee, is in the
+// header. Misuse the Rust side detects (a bad slot, a double retire in checking
+// mode) panics, which aborts the process at this boundary.
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_void, CStr};
+use std::ptr::null_mut;
+use std::sync::atomic::AtomicPtr;
+#[cfg(feature = "unordered-map")]
+use crate::structures::link_list::SortedUnorderedMap;
+use crate::trackers::MemoryTracker::{MemoryTracker, TrackerConfig, TrackerType};
+use crate::util::ThreadRegistry::ThreadToken;
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod Conformance;
+
+// Runs once a retired object can no longer be reached, with the object's
+// pointer and the context given to smr_retire
+pub type SmrDestructor = Option<unsafe extern "C" fn(ptr: *mut c_void, ctx: *mut c_void)>;
+
+// A pointer of the caller in a block of the tracker, so that every scheme can
+// stamp and protect it. C structures link these, not their own pointers.
+pub struct SmrObject {
+    ptr: *mut c_void,
+    destructor: SmrDestructor, // Set by smr_retire
+    ctx: *mut c_void,
+}
+
+// The pointer is the caller's, who promises it may be destroyed on any thread
+unsafe impl Send for SmrObject {}
+
+impl Drop for SmrObject {
+    fn drop(&mut self) {
+        if let Some(destructor) = self.destructor {
+            unsafe { destructor(self.ptr, self.ctx) };
+        }
+    }
+}
+
+pub struct SmrTracker {
+    tracker: MemoryTracker<SmrObject>,
+}
+
+pub struct SmrThread {
+    token: ThreadToken,
+}
+
+#[cfg(feature = "unordered-map")]
+pub struct SmrMap {
+    map: SortedUnorderedMap<u64, u64>,
+}
+
+// Unknown names and schemes whose feature is off give None
+fn tracker_type(name: *const c_char) -> Option<TrackerType> {
+    if name.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(name) }.to_str().ok()?;
+    TrackerType::lookup(name).ok()
+}
+
+fn thread(token: ThreadToken) -> *mut SmrThread {
+    Box::into_raw(Box::new(SmrThread { token }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smr_tracker_create(name: *const c_char) -> *mut SmrTracker {
+    match tracker_type(name) {
+        Some(tracker_type) => {
+            let tracker = MemoryTracker::new(&format!("{:?}", tracker_type), TrackerConfig::default());
+            Box::into_raw(Box::new(SmrTracker { tracker }))
+        }
+        None => null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smr_tracker_destroy(tracker: *mut SmrTracker) {
+    if tracker.is_null() {
+        return;
+    }
+    let mut tracker = Box::from_raw(tracker);
+    // Runs the destructor of everything still retired
+    tracker.tracker.drain();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smr_thread_register(tracker: *mut SmrTracker) -> *mut SmrThread {
+    thread((*tracker).tracker.register())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smr_thread_unregister(tracker: *mut SmrTracker, thread: *mut SmrThread) {
+    (*tracker).tracker.unregister(Box::from_raw(thread).token);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smr_pin(tracker: *mut SmrTracker, thread: *mut SmrThread) {
+    (*tracker).tracker.start_op(&(*thread).token);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smr_unpin(tracker: *mut SmrTracker, thread: *mut SmrThread) {
+    // Trackers made for C have no memory budget, there is nothing to report
+    let _ = (*tracker).tracker.end_op(&(*thread).token);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smr_object_new(tracker: *mut SmrTracker, thread: *mut SmrThread, ptr: *mut c_void)
+                                        -> *mut SmrObject {
+    let object = SmrObject { ptr, destructor: None, ctx: null_mut() };
+    (*tracker).tracker.alloc(object, &(*thread).token)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smr_object_ptr(object: *const SmrObject) -> *mut c_void {
+    (*object).ptr
+}
+
+// For an object no other thread has seen, its pointer stays the caller's
+#[no_mangle]
+pub unsafe extern "C" fn smr_object_free(tracker: *mut SmrTracker, object: *mut SmrObject) {
+    (*tracker).tracker.reclaim(object);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smr_protect(tracker: *mut SmrTracker, thread: *mut SmrThread,
+                                     location: *const AtomicPtr<SmrObject>, slot: usize) -> *mut SmrObject {
+    (*tracker).tracker.read(&*location, slot, &(*thread).token)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smr_retire(tracker: *mut SmrTracker, thread: *mut SmrThread, object: *mut SmrObject,
+                                    destructor: SmrDestructor, ctx: *mut c_void) {
+    // Readers that still hold the object only look at `ptr`
+    (*object).destructor = destructor;
+    (*object).ctx = ctx;
+    (*tracker).tracker.retire(object, &(*thread).token);
+}
+
+#[cfg(feature = "unordered-map")]
+#[no_mangle]
+pub unsafe extern "C" fn smr_map_create(tracker_name: *const c_char, bucket_count: usize) -> *mut SmrMap {
+    match tracker_type(tracker_name) {
+        Some(tracker_type) if bucket_count > 0 => {
+            let map = SortedUnorderedMap::new(bucket_count, &format!("{:?}", tracker_type), TrackerConfig::default());
+            Box::into_raw(Box::new(SmrMap { map }))
+        }
+        _ => null_mut(),
+    }
+}
+
+#[cfg(feature = "unordered-map")]
+#[no_mangle]
+pub unsafe extern "C" fn smr_map_destroy(map: *mut SmrMap) {
+    if !map.is_null() {
+        drop(Box::from_raw(map));
+    }
+}
+
+#[cfg(feature = "unordered-map")]
+#[no_mangle]
+pub unsafe extern "C" fn smr_map_register(map: *mut SmrMap) -> *mut SmrThread {
+    thread((*map).map.register())
+}
+
+#[cfg(feature = "unordered-map")]
+#[no_mangle]
+pub unsafe extern "C" fn smr_map_unregister(map: *mut SmrMap, thread: *mut SmrThread) {
+    (*map).map.unregister(Box::from_raw(thread).token);
+}
+
+#[cfg(feature = "unordered-map")]
+#[no_mangle]
+pub unsafe extern "C" fn smr_map_insert(map: *mut SmrMap, thread: *mut SmrThread, key: u64, value: u64) -> bool {
+    (*map).map.insert(key, value, &(*thread).token)
+}
+
+#[cfg(feature = "unordered-map")]
+#[no_mangle]
+pub unsafe extern "C" fn smr_map_get(map: *mut SmrMap, thread: *mut SmrThread, key: u64, value: *mut u64) -> bool {
+    match (*map).map.get(&key, &(*thread).token) {
+        Some(found) => {
+            if !value.is_null() {
+                *value = found;
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(feature = "unordered-map")]
+#[no_mangle]
+pub unsafe extern "C" fn smr_map_remove(map: *mut SmrMap, thread: *mut SmrThread, key: u64, value: *mut u64)
+                                        -> std::ffi::c_int {
+    match (*map).map.remove(&key, &(*thread).token) {
+        Ok(Some(removed)) => {
+            if !value.is_null() {
+                *value = removed;
+            }
+            1
+        }
+        Ok(None) => 0,
+        Err(_) => -1,
+    }
+}
@@ -0,0 +1,111 @@
+// The C interface driven from Rust the way include/smr.h tells C callers to use
+// it, against every tracker type the library is built with
+use std::ffi::{c_void, CString};
+use std::ptr::{null, null_mut};
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use crate::ffi::*;
+use crate::trackers::MemoryTracker::TrackerType;
+
+// Destructor given to smr_retire, `ctx` points to a counter of runs
+unsafe extern "C" fn count_destroyed(ptr: *mut c_void, ctx: *mut c_void) {
+    drop(Box::from_raw(ptr as *mut u64));
+    (*(ctx as *const AtomicUsize)).fetch_add(1, Ordering::SeqCst);
+}
+
+fn names() -> impl Iterator<Item = CString> {
+    TrackerType::enabled().map(|tracker_type| CString::new(format!("{:?}", tracker_type)).unwrap())
+}
+
+// A retired object's destructor runs once no thread is pinned, exactly once,
+// and an object freed before it was published leaves its pointer alone
+#[test]
+fn retired_objects_are_destroyed_once() {
+    for name in names() {
+        let destroyed = AtomicUsize::new(0);
+        let ctx = &destroyed as *const AtomicUsize as *mut c_void;
+        unsafe {
+            let tracker = smr_tracker_create(name.as_ptr());
+            assert!(!tracker.is_null(), "{:?}: not created", name);
+            let reader = smr_thread_register(tracker);
+            let writer = smr_thread_register(tracker);
+            let data = Box::into_raw(Box::new(7u64)) as *mut c_void;
+            let shared = AtomicPtr::new(smr_object_new(tracker, writer, data));
+
+            smr_pin(tracker, reader);
+            let object = smr_protect(tracker, reader, &shared, 0);
+            assert_eq!(smr_object_ptr(object), data, "{:?}: object lost its pointer", name);
+            smr_pin(tracker, writer);
+            shared.store(null_mut(), Ordering::SeqCst);
+            smr_retire(tracker, writer, object, Some(count_destroyed), ctx);
+            smr_unpin(tracker, writer);
+            for _ in 0..64 {
+                smr_pin(tracker, writer);
+                let churn = smr_object_new(tracker, writer, Box::into_raw(Box::new(0u64)) as *mut c_void);
+                smr_retire(tracker, writer, churn, Some(count_destroyed), ctx);
+                smr_unpin(tracker, writer);
+            }
+            assert_eq!(*(smr_object_ptr(object) as *const u64), 7, "{:?}: destroyed while protected", name);
+            smr_unpin(tracker, reader);
+
+            let unpublished = Box::into_raw(Box::new(9u64));
+            smr_object_free(tracker, smr_object_new(tracker, writer, unpublished as *mut c_void));
+            assert_eq!(*Box::from_raw(unpublished), 9, "{:?}: smr_object_free touched the pointer", name);
+
+            smr_thread_unregister(tracker, reader);
+            smr_thread_unregister(tracker, writer);
+            smr_tracker_destroy(tracker);
+        }
+        assert_eq!(destroyed.load(Ordering::SeqCst), 65, "{:?}: destructors did not run exactly once", name);
+    }
+}
+
+// Create, insert, get, remove and destroy through the map functions, with and
+// without a place for the value
+#[cfg(feature = "unordered-map")]
+#[test]
+fn map_operations_round_trip() {
+    for name in names() {
+        unsafe {
+            let map = smr_map_create(name.as_ptr(), 4);
+            assert!(!map.is_null(), "{:?}: map not created", name);
+            let thread = smr_map_register(map);
+            for key in 0..32 {
+                assert!(smr_map_insert(map, thread, key, key * 3), "{:?}: key {} present", name, key);
+            }
+            assert!(!smr_map_insert(map, thread, 5, 0), "{:?}: duplicate key inserted", name);
+
+            let mut value = 0;
+            assert!(smr_map_get(map, thread, 5, &mut value) && value == 15, "{:?}: key 5 not found", name);
+            assert!(smr_map_get(map, thread, 6, null_mut()), "{:?}: key 6 not found without a value", name);
+            assert!(!smr_map_get(map, thread, 99, &mut value), "{:?}: absent key found", name);
+
+            assert_eq!(smr_map_remove(map, thread, 5, &mut value), 1, "{:?}: key 5 not removed", name);
+            assert_eq!(value, 15, "{:?}: removed value", name);
+            assert_eq!(smr_map_remove(map, thread, 6, null_mut()), 1, "{:?}: key 6 not removed", name);
+            assert_eq!(smr_map_remove(map, thread, 5, &mut value), 0, "{:?}: key 5 removed twice", name);
+            assert!(!smr_map_get(map, thread, 5, &mut value), "{:?}: removed key found", name);
+
+            smr_map_unregister(map, thread);
+            smr_map_destroy(map);
+        }
+    }
+}
+
+// Null and unknown names give null handles, destroying a null handle does nothing
+#[test]
+fn null_handles_are_refused() {
+    let unknown = CString::new("Epoch").unwrap();
+    unsafe {
+        assert!(smr_tracker_create(null()).is_null());
+        assert!(smr_tracker_create(unknown.as_ptr()).is_null());
+        smr_tracker_destroy(null_mut());
+        #[cfg(feature = "unordered-map")]
+        {
+            let name = names().next().unwrap();
+            assert!(smr_map_create(null(), 4).is_null());
+            assert!(smr_map_create(unknown.as_ptr(), 4).is_null());
+            assert!(smr_map_create(name.as_ptr(), 0).is_null());
+            smr_map_destroy(null_mut());
+        }
+    }
+}
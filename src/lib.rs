@@ -0,0 +1,35 @@
+// Safe memory reclamation schemes and the concurrent structures built on them.
+// The modules are internal, the items re-exported here are the interface other
+// crates (and the benchmark harness in main.rs) use. Also built as a cdylib,
+// `ffi` is the C interface.
+//
+// Every scheme and data structure has a cargo feature. Helpers only some of
+// them use are compiled with those features.
+#[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+mod structures;
+mod trackers;
+mod util;
+pub mod ffi;
+
+// Trackers: pick a scheme by TrackerType or name, register each thread and
+// run operations under a Guard
+pub use trackers::MemoryTracker::{Guard, MemoryTracker, TrackerConfig, TrackerType, TrackerTypeError};
+#[cfg(feature = "hyaline")]
+pub use trackers::hyaline_alg::wide_head_lock_free;
+pub use util::ThreadRegistry::ThreadToken;
+// Configuration of a tracker
+pub use util::Backoff::BackoffStrategy;
+pub use util::MemoryBudget::{BudgetExceeded, BudgetPolicy, MemoryBudget};
+pub use util::RAllocator::AllocatorType;
+// Data structures, each behind its own feature
+#[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+pub use structures::{ConcurrentMap, LeakReport};
+#[cfg(feature = "unordered-map")]
+pub use structures::link_list::SortedUnorderedMap;
+#[cfg(feature = "split-ordered-map")]
+pub use structures::split_ordered::SplitOrderedMap;
+#[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+pub use util::KeyHasher::HasherType;
+// Reclamation counters and state exports
+pub use util::RetiredMonitorable::{RetiredMonitorable, RetiredStats};
+pub use util::StateExport::StateSnapshot;
@@ -8,31 +8,27 @@ use std::io::Write;
 use std::thread;
 use std::time::{Instant};
 use rand::Rng;
-use crate::run_test::link_list::SortedUnorderedMap;
+use project::ConcurrentMap;
 extern crate libc;
-use sys_info;
-mod link_list;
-
-struct ThreadInfo {
-    thread_id: i32,
-    //seed: u64,
-}
 
 pub struct GlobalTest {
     warmup: u32,
     num_threads: i32,
     contain_percent:f64,
-    list: SortedUnorderedMap<i64, i32>,
+    list: Arc<dyn ConcurrentMap<i64, i32>>, // Shared with the memory monitor
+    export: Option<String>, // Path prefix of the DOT and JSON snapshots written after the run
+    capped: bool, // The tracker has a memory budget
     //queue = Arc::new(Mutex::new(Vec::new()));
 }
 
 impl GlobalTest {
-    pub(crate) fn new(warmup: u32, num_threads: i32, contain_percent:f64) -> Self {
-        let list = SortedUnorderedMap::new(1, num_threads);
-        GlobalTest { warmup, num_threads, contain_percent, list}
+    pub(crate) fn new(warmup: u32, num_threads: i32, contain_percent:f64, list: Box<dyn ConcurrentMap<i64, i32>>,
+                      export: Option<String>, capped: bool) -> Self {
+        GlobalTest { warmup, num_threads, contain_percent, list: Arc::from(list), export, capped }
     }
 
     fn thread_main_debug(&self, contain_percent: f64) {
+        let token = self.list.register();
         let mut rng = rand::thread_rng();
        // println!("Thread PID {:?} with seed {:?}", thread_info.thread_id, thread_info.seed);
         for i in 1..3000 {
@@ -40,7 +36,7 @@ impl GlobalTest {
             let random_int: i64 = rng.gen_range(0..5);
             if random_float < (1.0 - contain_percent) / 2.0 {
                 //println!("Insert key {:?}", random_int);
-                if self.list.insert(random_int, i, 0) {
+                if self.list.insert(random_int, i, &token) {
                     //println!("Insert key {:?} success", random_int);
                 }else{
                     //println!("Insert key {:?} failed: duplicate", random_int);
@@ -49,17 +45,20 @@ impl GlobalTest {
             }
             if random_float >= (1.0 - contain_percent) / 2.0 && random_float < (1.0 - contain_percent) {
                 //println!("Remove {:?}", random_int);
-                self.list.remove(&random_int, 0);
+                // A remove rejected by the memory budget is just skipped
+                let _ = self.list.remove(&random_int, &token);
                 //println!("Remove key: {:?}", self.list.remove(&random_int, 0));
                 continue;
             }
             //println!("Get key {:?}", random_int);
             //println!("Get key : {:?}", self.list.get(&random_int, 0));
-            self.list.get(&random_int, 0);
+            self.list.get(&random_int, &token);
         }
+        self.list.unregister(token);
     }
 
-    fn thread_main(&self, thread_info: ThreadInfo, contain_percent: f64) {
+    fn thread_main(&self, contain_percent: f64) {
+        let token = self.list.register();
         let mut rng = rand::thread_rng();
         //println!("Thread PID {:?} with seed {:?}", thread_info.thread_id, thread_info.seed);
         for i in 1..50000 {
@@ -67,7 +66,7 @@ impl GlobalTest {
             let random_int: i64 = rng.gen_range(0..100);
             if random_float < (1.0 - contain_percent) / 2.0 {
                 //println!("Insert key {:?}", random_int);
-                if self.list.insert(random_int, i, thread_info.thread_id) {
+                if self.list.insert(random_int, i, &token) {
                     //println!("Insert key {:?} success", random_int);
                 }else{
                     //println!("Insert key {:?} failed: duplicate", random_int);
@@ -77,13 +76,15 @@ impl GlobalTest {
             if random_float >= (1.0 - contain_percent) / 2.0 && random_float < (1.0 - contain_percent) {
                 //println!("Remove {:?}", random_int);
                 //println!("Remove key: {:?}", self.list.remove(&random_int, thread_info.thread_id));
-                self.list.remove(&random_int, thread_info.thread_id);
+                // A remove rejected by the memory budget is just skipped
+                let _ = self.list.remove(&random_int, &token);
                 continue;
             }
             //println!("Get key {:?}", random_int);
             //println!("Get key : {:?}", self.list.get(&random_int, thread_info.thread_id));
-            self.list.get(&random_int, thread_info.thread_id);
+            self.list.get(&random_int, &token);
         }
+        self.list.unregister(token);
     }
 
     fn warm_memory(&self, megabytes: u32) -> i32 {
@@ -132,7 +133,7 @@ impl GlobalTest {
                 dealloc(p, layout);
             }
         }
-        return ret;
+        ret
     }
 
     pub(crate) fn run_test(self) {
@@ -148,6 +149,11 @@ impl GlobalTest {
             let start = Instant::now();
             let number_of_threads = self.num_threads;
             let contain_percent = self.contain_percent;
+            let list_clone = Arc::clone(&self.list);
+            // The snapshot thread reaches the map without locking the test
+            let (snapshot_list, capped) = (Arc::clone(&self.list), self.capped);
+            // Registered before the workers, so that the tracker counts it from the start
+            let snapshot_token = self.export.as_ref().map(|_| snapshot_list.register());
             // Wrap self in an Arc and Mutex for safe shared ownership
             let self_arc = Arc::new(Mutex::new(self));  // Wrap `self` in an Arc<Mutex<YourStruct>>
 
@@ -171,7 +177,9 @@ impl GlobalTest {
                                 // 输出可用内存，以字节为单位
                                 //let mut available_memory= ;
                                 let mut data = memory_data_clone.lock().unwrap();
-                                data.push(info.avail);
+                                // Garbage the tracker is holding back at this point
+                                let unreclaimed = list_clone.report_retired().unreclaimed_bytes();
+                                data.push((info.avail, unreclaimed));
                                 //println!("Available memory: {} bytes", info.avail);
                             },
                             Err(e) => eprintln!("Error fetching memory info: {}", e)
@@ -183,21 +191,36 @@ impl GlobalTest {
                 }
             });
 
-            for i in 0..number_of_threads {
-                let pid = i;  // thread id
-                //let seed: u64 = rand::thread_rng().random();
-
-                let thread_info = ThreadInfo { thread_id: pid};
+            for _ in 0..number_of_threads {
+                // Each thread registers with the map itself, no thread id is handed out here
                 let self_clone = Arc::clone(&self_arc); // Clone the Arc to share ownership across threads
                 // Create a thread and give it thread_info
                 let handle = thread::spawn(move || {
                     let self_locked = self_clone.lock().unwrap();
-                    self_locked.thread_main(thread_info, contain_percent);  // Each thread run `thread_main` independently
+                    self_locked.thread_main(contain_percent);  // Each thread run `thread_main` independently
                 });
 
                 handles.push(handle);
             }
 
+            // With an export, the snapshot thread enters an operation once the first
+            // worker is done and stays in it until the others are, so the retirement
+            // lists hold what they retire meanwhile. Under a memory budget it would
+            // hold back the garbage the workers wait on, it only enters after them.
+            let mut handles = handles.into_iter();
+            let snapshot = snapshot_token.as_ref().map(|token| {
+                if capped {
+                    handles.by_ref().for_each(|handle| handle.join().unwrap());
+                } else if let Some(first) = handles.next() {
+                    first.join().unwrap();
+                }
+                snapshot_list.snapshot(token, &mut || handles.by_ref().for_each(|handle| handle.join().unwrap()))
+            });
+            if let Some(token) = snapshot_token {
+                snapshot_list.unregister(token);
+            }
+            drop(snapshot_list);
+
             // Waiting for all the threads finish
             for handle in handles {
                 handle.join().unwrap();
@@ -207,12 +230,39 @@ impl GlobalTest {
             let duration = start.elapsed().as_nanos();
             println!("Execution time: {:?} nanosecond", duration);
 
+            // All workers are done, free what is left in the map and check for leaks
+            let mut test = match Arc::try_unwrap(self_arc) {
+                Ok(test) => test.into_inner().unwrap(),
+                Err(_) => panic!("Worker threads still hold the test"),
+            };
+
+            // Per-thread counters of the tracker, slots that were never used are skipped
+            println!("Tracker statistics:");
+            for (tid, stats) in test.list.collect_retired_stats() {
+                if stats.allocated != 0 || stats.retired != 0 {
+                    println!("  Thread {}: {}", tid, stats);
+                }
+            }
+            println!("  Total: {}", test.list.report_retired());
+            // Frequencies an adaptive tracker settled on are only known at the end
+            println!("Tracker: {}", test.list.tracker_info());
+
+            // State of the map and the tracker taken above, before teardown
+            if let (Some(prefix), Some(snapshot)) = (&test.export, snapshot) {
+                std::fs::write(format!("{}.dot", prefix), snapshot.to_dot()).expect("error writing DOT snapshot");
+                std::fs::write(format!("{}.json", prefix), snapshot.to_json()).expect("error writing JSON snapshot");
+                println!("State written to {}.dot and {}.json", prefix, prefix);
+            }
+
+            let list = Arc::get_mut(&mut test.list).expect("Memory monitor still holds the map");
+            println!("{}", list.teardown());
+
             // 输出内存数据到 CSV 文件
             let data_file = "memory_data.csv";
             let mut file = File::create(data_file).expect("file creation error");
             let memory_data = memory_data.lock().unwrap();
-            for (i, &value) in memory_data.iter().enumerate() {
-                writeln!(file, "{},{:.2}", i as f64 * 0.2, value as i64).expect("error writing memory data");
+            for (i, &(available, unreclaimed)) in memory_data.iter().enumerate() {
+                writeln!(file, "{},{:.2},{}", i as f64 * 0.2, available as i64, unreclaimed).expect("error writing memory data");
             }
 
         }
@@ -0,0 +1,84 @@
+// Concurrent data structures whose nodes are reclaimed through a MemoryTracker,
+// each behind its own cargo feature
+use std::fmt;
+
+use crate::util::MemoryBudget::BudgetExceeded;
+use crate::util::RetiredMonitorable::RetiredMonitorable;
+use crate::util::StateExport::StateSnapshot;
+use crate::util::ThreadRegistry::ThreadToken;
+
+#[cfg(feature = "unordered-map")]
+pub(crate) mod link_list;
+#[cfg(feature = "split-ordered-map")]
+pub(crate) mod split_ordered;
+#[cfg(all(test, any(feature = "unordered-map", feature = "split-ordered-map")))]
+#[allow(non_snake_case)]
+mod Conformance;
+
+// The operations every map offers, so that the harness and the tests can run
+// any of them. Each map has the same methods inherently.
+pub trait ConcurrentMap<K, V>: RetiredMonitorable + Send + Sync {
+    fn register(&self) -> ThreadToken;
+    fn unregister(&self, token: ThreadToken);
+    // false if the key is already present
+    fn insert(&self, key: K, value: V, token: &ThreadToken) -> bool;
+    fn get(&self, key: &K, token: &ThreadToken) -> Option<V>;
+    fn remove(&self, key: &K, token: &ThreadToken) -> Result<Option<V>, BudgetExceeded>;
+    fn tracker_info(&self) -> String;
+    // Inside an operation of the caller while `during` runs, see the maps
+    fn snapshot(&self, token: &ThreadToken, during: &mut dyn FnMut()) -> StateSnapshot;
+    fn teardown(&mut self) -> LeakReport;
+}
+
+// Node accounting of a map, taken when the map is torn down
+pub struct LeakReport {
+    pub allocated: u64,   // Nodes allocated by insert
+    pub retired: u64,     // Nodes handed to the tracker
+    pub reclaimed: u64,   // Retired nodes the tracker has freed
+    pub freed: u64,       // Nodes freed without the tracker (failed inserts, teardown)
+    pub active_threads: i64, // Threads that never left the tracker
+}
+
+impl LeakReport {
+    // Nodes that were allocated and never freed
+    pub fn outstanding(&self) -> u64 {
+        self.allocated - self.reclaimed - self.freed
+    }
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Leak report: allocated {}, retired {}, reclaimed {}, freed {}, outstanding {}",
+               self.allocated, self.retired, self.reclaimed, self.freed, self.outstanding())?;
+        if self.active_threads != 0 {
+            write!(f, " ({} threads still active)", self.active_threads)?;
+        }
+        Ok(())
+    }
+}
+
+// Reservation slots used while walking a list hand over hand: the node whose
+// `next` is read, the node read from it and that node's successor
+pub(crate) const PREV: usize = 0;
+pub(crate) const CUR: usize = 1;
+pub(crate) const NEXT: usize = 2;
+
+// The low bit of a node's `next` marks the node as deleted (Harris-Michael). A
+// marked node is only waiting to be unlinked, nothing is linked after it anymore.
+// Nodes are at least 8-byte aligned, so the bit is free.
+const MARK: usize = 1;
+
+pub(crate) fn is_marked<T>(ptr: *mut T) -> bool {
+    ptr.addr() & MARK != 0
+}
+
+pub(crate) fn marked<T>(ptr: *mut T) -> *mut T {
+    ptr.map_addr(|addr| addr | MARK)
+}
+
+pub(crate) fn unmarked<T>(ptr: *mut T) -> *mut T {
+    ptr.map_addr(|addr| addr & !MARK)
+}
+
+// Held by a node while it is alive, poisoned once it is freed in use-after-free mode
+pub(crate) const NODE_CANARY: u64 = 0x5AFE_C0DE_5AFE_C0DE;
@@ -0,0 +1,391 @@
+// Properties every data structure has to provide, against every tracker type
+// the dispatcher can build. Keys share buckets so that threads keep inserting
+// and removing next to each other's nodes.
+#[cfg(feature = "unordered-map")]
+use std::process::Command;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use crate::structures::ConcurrentMap;
+#[cfg(feature = "unordered-map")]
+use crate::structures::{marked, unmarked};
+#[cfg(feature = "unordered-map")]
+use crate::structures::link_list::{Node, SortedUnorderedMap};
+#[cfg(feature = "split-ordered-map")]
+use crate::structures::split_ordered::SplitOrderedMap;
+use crate::trackers::MemoryTracker::{TrackerConfig, TrackerType};
+use crate::util::KeyHasher::HasherType;
+
+const THREADS: u64 = 4;
+const KEYS_PER_THREAD: u64 = 500;
+const KEYS_PER_ROUND: u64 = 4;
+const ROUNDS: usize = 5000;
+const BUCKETS: usize = 1;
+
+type MapBuilder<K, V> = fn(usize, HasherType, &str, TrackerConfig) -> Box<dyn ConcurrentMap<K, V>>;
+
+// Every map compiled in, by name
+fn maps<K, V>() -> Vec<(&'static str, MapBuilder<K, V>)>
+where
+    K: Ord + std::hash::Hash + Clone + std::fmt::Debug + Send + Sync + 'static,
+    V: Clone + std::fmt::Debug + Send + Sync + 'static,
+{
+    let mut maps: Vec<(&'static str, MapBuilder<K, V>)> = Vec::new();
+    #[cfg(feature = "unordered-map")]
+    maps.push(("SortedUnorderedMap", |buckets, hasher, tracker, config| {
+        Box::new(SortedUnorderedMap::with_hasher(buckets, hasher, tracker, config))
+    }));
+    #[cfg(feature = "split-ordered-map")]
+    maps.push(("SplitOrderedMap", |buckets, hasher, tracker, config| {
+        Box::new(SplitOrderedMap::with_hasher(buckets, hasher, tracker, config))
+    }));
+    maps
+}
+
+fn for_each_map(check: impl Fn(&dyn ConcurrentMap<u64, u64>, &str)) {
+    for (name, build) in maps() {
+        for tracker_type in TrackerType::enabled() {
+            let label = format!("{} with {:?}", name, tracker_type);
+            let config = TrackerConfig { epoch_freq: 1, empty_freq: 1, ..TrackerConfig::default() };
+            let mut map = build(BUCKETS, HasherType::SipHash, &format!("{:?}", tracker_type), config);
+            check(map.as_ref(), &label);
+            let report = map.teardown();
+            assert_eq!(report.outstanding(), 0, "{}: {}", label, report);
+        }
+    }
+}
+
+// Each thread keeps inserting and removing a few keys of its own, interleaved
+// with those of the others, so nodes are linked after and unlinked before nodes
+// another thread is removing. An insert lost that way shows up as a failed
+// remove, a removed node that is linked again as a failed insert.
+#[test]
+fn concurrent_updates_are_not_lost() {
+    for_each_map(|map, label| {
+        thread::scope(|scope| {
+            for t in 0..THREADS {
+                scope.spawn(move || {
+                    let token = map.register();
+                    let keys = (0..KEYS_PER_ROUND).map(|i| i * THREADS + t);
+                    for _ in 0..ROUNDS {
+                        for key in keys.clone() {
+                            assert!(map.insert(key, key, &token), "{}: key {} still present", label, key);
+                        }
+                        for key in keys.clone() {
+                            assert_eq!(map.remove(&key, &token).unwrap(), Some(key), "{}: key {} lost", label, key);
+                        }
+                    }
+                    map.unregister(token);
+                });
+            }
+        });
+
+        let token = map.register();
+        for key in 0..THREADS * KEYS_PER_ROUND {
+            assert_eq!(map.get(&key, &token), None, "{}: removed key {} present", label, key);
+        }
+        map.unregister(token);
+    });
+}
+
+// Threads race to remove the same keys, each key is removed exactly once
+#[test]
+fn each_key_is_removed_once() {
+    for_each_map(|map, label| {
+        let token = map.register();
+        for key in 0..KEYS_PER_THREAD {
+            map.insert(key, key, &token);
+        }
+        map.unregister(token);
+
+        let removed = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    let token = map.register();
+                    for key in 0..KEYS_PER_THREAD {
+                        if map.remove(&key, &token).unwrap().is_some() {
+                            removed.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    map.unregister(token);
+                });
+            }
+        });
+        assert_eq!(removed.into_inner() as u64, KEYS_PER_THREAD, "{}: keys removed more or less than once", label);
+    });
+}
+
+// Keys spread over many buckets are found again with every hasher, negative
+// and colliding ones included
+#[test]
+fn every_hasher_finds_its_keys() {
+    for (name, build) in maps() {
+        for hasher in [HasherType::SipHash, HasherType::Multiplicative, HasherType::Identity] {
+            let tracker_type = TrackerType::enabled().next().unwrap();
+            let mut map = build(16, hasher, &format!("{:?}", tracker_type), TrackerConfig::default());
+            let token = map.register();
+            let keys: Vec<i64> = (-200..200).map(|i| i * 16).chain(0..100).collect();
+            for &key in &keys {
+                map.insert(key, key * 2, &token);
+            }
+            for &key in &keys {
+                assert_eq!(map.get(&key, &token), Some(key * 2), "{} with {}: key {} not found", name, hasher, key);
+            }
+            for &key in &keys {
+                map.remove(&key, &token).unwrap();
+            }
+            map.unregister(token);
+            assert_eq!(map.teardown().outstanding(), 0, "{} with {}: nodes leaked", name, hasher);
+        }
+    }
+}
+
+// The identity hash is the key for integers of every width and refuses keys
+// that are not a single integer instead of bucketing them all together
+#[test]
+fn identity_hasher_takes_only_integer_keys() {
+    assert_eq!(HasherType::Identity.hash(&7u8), 7);
+    assert_eq!(HasherType::Identity.hash(&-1i32), u32::MAX as u64);
+    assert_eq!(HasherType::Identity.hash(&42usize), 42);
+    assert!(panic::catch_unwind(|| HasherType::Identity.hash(&"key")).is_err());
+    assert!(panic::catch_unwind(|| HasherType::Identity.hash(&String::from("key"))).is_err());
+    assert!(panic::catch_unwind(|| HasherType::Identity.hash(&(1u64, 2u64))).is_err());
+    assert!(panic::catch_unwind(|| HasherType::Identity.hash(&())).is_err());
+}
+
+// Threads insert disjoint keys while the table keeps doubling under them, and
+// every key is found and removed afterwards from the grown table
+#[cfg(feature = "split-ordered-map")]
+#[test]
+fn split_ordered_map_grows_without_losing_keys() {
+    for tracker_type in TrackerType::enabled() {
+        let config = TrackerConfig { epoch_freq: 1, empty_freq: 1, ..TrackerConfig::default() };
+        let mut map = SplitOrderedMap::new(1, &format!("{:?}", tracker_type), config);
+        thread::scope(|scope| {
+            for t in 0..THREADS {
+                let map = &map;
+                scope.spawn(move || {
+                    let token = map.register();
+                    for key in (0..KEYS_PER_THREAD).map(|i| i * THREADS + t) {
+                        assert!(map.insert(key, key, &token), "{:?}: key {} already present", tracker_type, key);
+                        assert_eq!(map.get(&key, &token), Some(key), "{:?}: key {} lost while growing", tracker_type, key);
+                    }
+                    map.unregister(token);
+                });
+            }
+        });
+        assert!(map.bucket_count() >= (THREADS * KEYS_PER_THREAD / 2) as usize, "{:?}: table did not grow", tracker_type);
+
+        let token = map.register();
+        for key in 0..THREADS * KEYS_PER_THREAD {
+            assert_eq!(map.remove(&key, &token).unwrap(), Some(key), "{:?}: key {} lost", tracker_type, key);
+        }
+        map.unregister(token);
+        let report = map.teardown();
+        assert_eq!(report.outstanding(), 0, "{:?}: {}", tracker_type, report);
+    }
+}
+
+// Environment variable that makes a use-after-free test run its scenario, with
+// the tracker type to run it on
+#[cfg(feature = "unordered-map")]
+const UAF_SCENARIO: &str = "UAF_SCENARIO";
+
+// Run `scenario` for every tracker type, each in a child process of this test
+// binary, where it must be reported and abort the run. Returns the reports.
+// Inside the child `test` itself is the child, it runs the scenario on a thread
+// named "uaf-probe".
+#[cfg(feature = "unordered-map")]
+fn use_after_free_reports(test: &str, scenario: fn(&str)) -> Vec<(TrackerType, String)> {
+    if let Ok(tracker_type) = std::env::var(UAF_SCENARIO) {
+        thread::Builder::new().name("uaf-probe".to_string()).spawn(move || scenario(&tracker_type))
+            .unwrap().join().unwrap();
+        panic!("use after free went unreported");
+    }
+    TrackerType::enabled().map(|tracker_type| {
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args([test, "--exact", "--nocapture", "--test-threads=1"])
+            .env(UAF_SCENARIO, format!("{:?}", tracker_type))
+            .output().unwrap();
+        assert!(!output.status.success(), "{:?}: child survived the use after free", tracker_type);
+        (tracker_type, String::from_utf8_lossy(&output.stderr).into_owned())
+    }).collect()
+}
+
+// A map in use-after-free mode with keys 1 and 2 in a single bucket, where key 2
+// has been removed and its node freed into the quarantine. Returns the map, the
+// node of key 1 and the freed node.
+#[cfg(feature = "unordered-map")]
+type NodePtr = *mut Node<u64, u64>;
+
+#[cfg(feature = "unordered-map")]
+fn map_with_freed_node(tracker_type: &str, quarantine: usize) -> (SortedUnorderedMap<u64, u64>, NodePtr, NodePtr) {
+    let config = TrackerConfig { epoch_freq: 1, empty_freq: 1, quarantine, ..TrackerConfig::default() };
+    let map = SortedUnorderedMap::new(1, tracker_type, config);
+    let token = map.register();
+    map.insert(1, 1, &token);
+    map.insert(2, 2, &token);
+    let nodes = &map.snapshot(&token, &mut || {}).buckets[0];
+    let (kept, freed) = (nodes[0].addr as NodePtr, nodes[1].addr as NodePtr);
+    map.remove(&2, &token).unwrap();
+    // One more remove scans the node of key 2 once its own operation has left
+    map.insert(3, 3, &token);
+    map.remove(&3, &token).unwrap();
+    map.unregister(token);
+    (map, kept, freed)
+}
+
+// A write to a freed node is found when its block leaves the quarantine, the
+// report names the operation that freed it and the thread
+#[cfg(feature = "unordered-map")]
+#[test]
+fn quarantined_write_is_reported() {
+    let reports = use_after_free_reports("structures::Conformance::quarantined_write_is_reported", |tracker_type| {
+        let (map, _, freed) = map_with_freed_node(tracker_type, 16);
+        unsafe { (*freed).next.store(std::ptr::null_mut(), Ordering::SeqCst) };
+        let token = map.register();
+        for key in 10..100 {
+            map.insert(key, key, &token);
+            map.remove(&key, &token).unwrap();
+        }
+    });
+    for (tracker_type, report) in reports {
+        assert!(report.contains("write to a freed block") && report.contains("during remove")
+                && report.contains("uaf-probe"), "{:?}: unexpected report {}", tracker_type, report);
+    }
+}
+
+// A get that reaches a freed node finds its canary poisoned, the report names
+// the operation, the thread's slot and the thread
+#[cfg(feature = "unordered-map")]
+#[test]
+fn poisoned_canary_is_reported() {
+    let reports = use_after_free_reports("structures::Conformance::poisoned_canary_is_reported", |tracker_type| {
+        let (map, kept, freed) = map_with_freed_node(tracker_type, 4096);
+        // Link the freed node again, as a reclamation bug would have left it
+        unsafe { (*kept).next.store(freed, Ordering::SeqCst) };
+        let token = map.register();
+        map.get(&2, &token);
+    });
+    for (tracker_type, report) in reports {
+        assert!(report.contains("Use after free: get on slot") && report.contains("uaf-probe"),
+                "{:?}: unexpected report {}", tracker_type, report);
+    }
+}
+
+// A small map with two buckets and one node marked but not yet unlinked, as a
+// remove leaves it until the next walk. The JSON export holds every bucket in
+// order with its keys and flags the marked node, the DOT export has a node per
+// map node and the edges of every chain.
+#[cfg(feature = "unordered-map")]
+#[test]
+fn export_shows_buckets_keys_and_marked_nodes() {
+    for tracker_type in TrackerType::enabled() {
+        let mut map = SortedUnorderedMap::with_hasher(2, HasherType::Identity, &format!("{:?}", tracker_type),
+                                                      TrackerConfig::default());
+        let token = map.register();
+        for key in 1..=4u64 {
+            map.insert(key, key * 10, &token);
+        }
+        let deleted = map.snapshot(&token, &mut || {}).buckets[1][1].addr as NodePtr;
+        let next = unsafe { &(*deleted).next };
+        next.store(marked(next.load(Ordering::SeqCst)), Ordering::SeqCst);
+
+        let snapshot = map.snapshot(&token, &mut || {});
+        map.unregister(token);
+        let json: serde_json::Value = serde_json::from_str(&snapshot.to_json()).unwrap();
+        let buckets = json["buckets"].as_array().unwrap();
+        let chains: Vec<Vec<(&str, &str, bool)>> = buckets.iter().map(|bucket| {
+            bucket.as_array().unwrap().iter().map(|node| {
+                (node["key"].as_str().unwrap(), node["value"].as_str().unwrap(), node["marked"].as_bool().unwrap())
+            }).collect()
+        }).collect();
+        assert_eq!(chains, vec![vec![("2", "20", false), ("4", "40", false)], vec![("1", "10", false), ("3", "30", true)]],
+                   "{:?}: JSON buckets", tracker_type);
+        assert!(json["tracker"].as_str().unwrap().starts_with(&format!("{:?}", tracker_type)));
+
+        let dot = snapshot.to_dot();
+        let lines: Vec<&str> = dot.lines().map(str::trim).collect();
+        for (i, bucket) in buckets.iter().enumerate() {
+            let mut previous = format!("bucket{}", i);
+            for node in bucket.as_array().unwrap() {
+                let addr = u64::from_str_radix(node["addr"].as_str().unwrap().trim_start_matches("0x"), 16).unwrap();
+                let name = format!("n_{:x}", addr);
+                let declared = lines.iter().find(|line| line.starts_with(&format!("{} [", name)))
+                    .unwrap_or_else(|| panic!("{:?}: no DOT node for {}", tracker_type, name));
+                assert_eq!(declared.contains("fillcolor"), node["marked"].as_bool().unwrap(),
+                           "{:?}: marked node drawn wrong: {}", tracker_type, declared);
+                let edge = format!("{} -> {};", previous, name);
+                assert!(lines.contains(&edge.as_str()), "{:?}: no DOT edge {}", tracker_type, edge);
+                previous = name;
+            }
+        }
+        let chain_edges = lines.iter().filter(|line| line.starts_with("bucket") || line.starts_with("n_"))
+            .filter(|line| line.contains(" -> ")).count();
+        assert_eq!(chain_edges, 4, "{:?}: DOT chains have extra edges", tracker_type);
+
+        next.store(unmarked(next.load(Ordering::SeqCst)), Ordering::SeqCst);
+        assert_eq!(map.teardown().outstanding(), 0, "{:?}: nodes leaked", tracker_type);
+    }
+}
+
+// A reader takes a snapshot while a writer removes three keys. The reader's own
+// reference keeps every node retired meanwhile, so the export lists each of them
+// with an NRef of one: the writer has left, only the reader still has to pass.
+// Hyaline inserts each node on its own into the shared list. Hyaline-1 inserts
+// batches of one member per registered thread, the third node is still pending.
+#[cfg(all(feature = "unordered-map", feature = "hyaline"))]
+#[test]
+fn export_holds_the_nodes_retired_while_pinned() {
+    for tracker_type in [TrackerType::Hyaline, TrackerType::Hyaline1] {
+        let config = TrackerConfig { batch_size: 1, ..TrackerConfig::default() };
+        let mut map = SortedUnorderedMap::with_hasher(1, HasherType::Identity, &format!("{:?}", tracker_type), config);
+        let reader = map.register();
+        let writer = map.register();
+        for key in 1..=4u64 {
+            map.insert(key, key, &writer);
+        }
+        let nodes: Vec<usize> = map.snapshot(&writer, &mut || {}).buckets[0].iter().map(|node| node.addr).collect();
+
+        let snapshot = map.snapshot(&reader, &mut || {
+            for key in 1..=3u64 {
+                map.remove(&key, &writer).unwrap();
+            }
+        });
+        let retirement = snapshot.retirement.expect("Hyaline exports its retirement lists");
+        let lists: Vec<_> = retirement.lists.iter().filter(|list| !list.entries.is_empty()).collect();
+        assert_eq!(lists.len(), 1, "{:?}: entries outside of the reader's list", tracker_type);
+        let list = lists[0];
+        assert_eq!(list.href, 1, "{:?}: only the reader is inside an operation", tracker_type);
+        assert_eq!(list.hptr, list.entries[0].addr, "{:?}: HPtr is not the newest entry", tracker_type);
+        assert!(!list.truncated, "{:?}: the list was empty when the reader entered", tracker_type);
+        assert!(list.entries.iter().all(|entry| entry.nref == 1), "{:?}: NRef other than the reader's", tracker_type);
+
+        // Entries are headers, which sit at a fixed offset before their node
+        let retired: Vec<usize> = match tracker_type {
+            TrackerType::Hyaline => {
+                assert!(retirement.pending.is_empty(), "{:?}: batches of one are inserted at once", tracker_type);
+                list.entries.iter().map(|entry| entry.addr).collect()
+            }
+            _ => {
+                assert_eq!(list.entries.len(), 1, "{:?}: one batch inserted", tracker_type);
+                let entry = &list.entries[0];
+                let pending: Vec<usize> = retirement.pending.iter().flat_map(|(_, batch)| batch.iter().copied()).collect();
+                assert_eq!(pending.len(), 1, "{:?}: the third node is not pending", tracker_type);
+                [entry.addr].into_iter().chain(entry.batch.iter().copied()).chain(pending).collect()
+            }
+        };
+        let removed = &nodes[..3];
+        let offset = removed.iter().min().unwrap() - retired.iter().min().unwrap();
+        let mut found: Vec<usize> = retired.iter().map(|addr| addr + offset).collect();
+        let mut removed = removed.to_vec();
+        found.sort_unstable();
+        removed.sort_unstable();
+        assert_eq!(found, removed, "{:?}: the exported entries are not the removed nodes", tracker_type);
+
+        map.unregister(writer);
+        map.unregister(reader);
+        assert_eq!(map.teardown().outstanding(), 0, "{:?}: nodes leaked", tracker_type);
+    }
+}
@@ -0,0 +1,409 @@
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use std::hash::Hash;
+use std::fmt::Debug;
+
+use crate::structures::{is_marked, marked, unmarked, ConcurrentMap, LeakReport, CUR, NEXT, NODE_CANARY, PREV};
+use crate::trackers::MemoryTracker::{Guard, MemoryTracker, TrackerConfig};
+use crate::util::Backoff::BackoffStrategy;
+use crate::util::KeyHasher::HasherType;
+use crate::util::MemoryBudget::BudgetExceeded;
+use crate::util::Quarantine::{report_use_after_free, OperationScope};
+use crate::util::RetiredMonitorable::{RetiredMonitorable, RetiredStats};
+use crate::util::StateExport::{NodeState, StateSnapshot};
+use crate::util::ThreadRegistry::ThreadToken;
+
+// Node struct. Nodes are allocated by the tracker, which keeps whatever the
+// reclamation scheme needs per node next to them.
+pub(crate) struct Node<K, V> {
+    canary: u64, // NODE_CANARY while the node is alive, poisoned once it is freed in use-after-free mode
+    pub(crate) key: K,
+    pub(crate) value: V,
+    pub(crate) next: AtomicPtr<Node<K, V>>,
+}
+
+impl<K, V> Node<K, V> {
+    // create a new node
+    pub(crate) fn new(key: K, value: V, next: *mut Node<K, V>) -> Node<K, V> {
+        Node { canary: NODE_CANARY, key, value, next: AtomicPtr::new(next) }
+    }
+}
+
+// SortedUnorderedMap struct
+pub struct SortedUnorderedMap<K, V> {
+    tracker: MemoryTracker<Node<K, V>>,
+    buckets: Vec<AtomicPtr<Node<K, V>>>,
+    //layout: Layout,
+    bucket_count: usize,
+    freed: AtomicU64,     // Nodes freed without going through the tracker
+    backoff: BackoffStrategy, // Wait between retries of a failed CAS on a link
+    uaf_checks: bool,     // Check the canary of every node before using it
+    hasher: HasherType,   // Picks the bucket of a key
+}
+
+impl<K, V> SortedUnorderedMap<K, V>
+where
+    K: Ord + Hash + Clone + Debug + Send + 'static,
+    V: Clone + Debug + Send + 'static,
+{
+    // `tracker_type` names the reclamation scheme, see trackers::MemoryTracker.
+    // Keys are hashed with SipHash.
+    pub fn new(bucket_count: usize, tracker_type: &str, config: TrackerConfig) -> Self {
+        SortedUnorderedMap::with_hasher(bucket_count, HasherType::SipHash, tracker_type, config)
+    }
+
+    pub fn with_hasher(bucket_count: usize, hasher: HasherType, tracker_type: &str, config: TrackerConfig) -> Self {
+        assert!(bucket_count > 0, "A map needs at least one bucket");
+        // find holds prev, cur and next at once
+        let config = TrackerConfig { slot_num: config.slot_num.max(NEXT + 1), ..config };
+        let mut buckets = Vec::with_capacity(bucket_count);
+        let backoff = config.backoff;
+        let uaf_checks = config.quarantine > 0;
+        let tracker = MemoryTracker::new(tracker_type, config);
+        for _ in 0..bucket_count {
+            buckets.push(AtomicPtr::new(null_mut()));
+        }
+        //let _layout = Layout::new::<Node<K, V>>();
+        SortedUnorderedMap {tracker, buckets, bucket_count, freed: AtomicU64::new(0), backoff, uaf_checks, hasher}
+    }
+
+    // Claim a slot for the calling thread. The token is passed to every operation
+    // and handed back to `unregister` when the thread is done with the map.
+    pub fn register(&self) -> ThreadToken {
+        self.tracker.register()
+    }
+
+    pub fn unregister(&self, token: ThreadToken) {
+        self.tracker.unregister(token);
+    }
+
+    fn hash(&self, key: &K) -> usize {
+        (self.hasher.hash(key) % self.bucket_count as u64) as usize
+    }
+
+    pub fn insert(&self, key: K, value: V, token: &ThreadToken) -> bool {
+        let _scope = self.uaf_checks.then(|| OperationScope::enter("insert"));
+        let guard = self.tracker.pin(token);
+        let bucket = &self.buckets[self.hash(&key)];
+        let mut value = Some(value);
+        // Allocated once the insertion point is found, kept across retries
+        let mut new_node: *mut Node<K, V> = null_mut();
+        let mut backoff = self.backoff.start();
+
+        loop {
+            let (prev, cur, found) = self.find(&guard, bucket, &key, "insert", token);
+            if found {
+                // Another thread inserted the key while we were retrying
+                if !new_node.is_null() {
+                    unsafe { self.free_node(new_node) };
+                }
+                return false;
+            }
+            match value.take() {
+                Some(value) => new_node = self.tracker.alloc(Node::new(key.clone(), value, cur), token),
+                None => unsafe { (*new_node).next.store(cur, Ordering::Relaxed) },
+            }
+            // Fails if a node was linked after prev or prev itself was deleted
+            if prev.compare_exchange(cur, new_node, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
+                return true;
+            }
+            backoff.failed();
+        }
+    }
+
+    pub fn get(&self, key: &K, token: &ThreadToken) -> Option<V> {
+        let _scope = self.uaf_checks.then(|| OperationScope::enter("get"));
+        let guard = self.tracker.pin(token);
+        let bucket = &self.buckets[self.hash(key)];
+        let (_, cur, found) = self.find(&guard, bucket, key, "get", token);
+        found.then(|| unsafe { self.node(cur, "get", token) }.value.clone())
+    }
+
+    // Dereference a node read during `op`. In use-after-free mode a node whose
+    // canary is gone, i.e. one that was freed and poisoned, aborts the run.
+    unsafe fn node<'a>(&self, ptr: *mut Node<K, V>, op: &str, token: &ThreadToken) -> &'a Node<K, V> {
+        if self.uaf_checks && (*ptr).canary != NODE_CANARY {
+            report_use_after_free(ptr as *const u8, &format!("{} on slot {}", op, token.index()));
+        }
+        &*ptr
+    }
+
+    // Walk `bucket` to the first node whose key is not below `key`. Returns the
+    // link that points to it, the node (null at the end of the list) and whether
+    // its key is `key`; the node is protected in CUR and the owner of the link in
+    // PREV. Marked nodes on the way are unlinked and retired by whoever unlinks
+    // them, so no thread ever continues from a deleted node.
+    fn find<'a>(&'a self, guard: &Guard<'_, Node<K, V>>, bucket: &'a AtomicPtr<Node<K, V>>, key: &K, op: &str,
+                token: &ThreadToken) -> (&'a AtomicPtr<Node<K, V>>, *mut Node<K, V>, bool) {
+        let mut backoff = self.backoff.start();
+        'retry: loop {
+            let mut prev = bucket;
+            let mut cur = guard.read(prev, CUR); // A bucket head is never marked
+            loop {
+                if cur.is_null() {
+                    return (prev, cur, false);
+                }
+                let cur_node = unsafe { self.node(cur, op, token) };
+                let next = guard.read(&cur_node.next, NEXT);
+                // cur must still be linked from prev, else next may already be gone
+                if prev.load(Ordering::SeqCst) != cur {
+                    backoff.failed();
+                    continue 'retry;
+                }
+                if is_marked(next) {
+                    // cur is deleted, help unlink it
+                    let next = unmarked(next);
+                    if prev.compare_exchange(cur, next, Ordering::SeqCst, Ordering::Relaxed).is_err() {
+                        backoff.failed();
+                        continue 'retry;
+                    }
+                    // Our CAS unlinked cur
+                    unsafe { self.retire_unlinked(guard, cur) };
+                    guard.transfer(NEXT, CUR);
+                    cur = next;
+                    continue;
+                }
+                if cur_node.key >= *key {
+                    return (prev, cur, cur_node.key == *key);
+                }
+                // cur becomes prev and next becomes cur, both stay protected
+                guard.transfer(CUR, PREV);
+                guard.transfer(NEXT, CUR);
+                prev = &cur_node.next;
+                cur = next;
+            }
+        }
+    }
+
+    // Whether `node` is linked in any bucket. Only meaningful while no other
+    // thread changes the map.
+    fn reachable(&self, node: *mut Node<K, V>) -> bool {
+        self.buckets.iter().any(|bucket| {
+            let mut cur = bucket.load(Ordering::SeqCst);
+            while !cur.is_null() {
+                if cur == node {
+                    return true;
+                }
+                cur = unmarked(unsafe { (*cur).next.load(Ordering::SeqCst) });
+            }
+            false
+        })
+    }
+
+    // Hand a node to the tracker right after this thread unlinked it. Only the
+    // thread whose CAS unlinked `node` may call this, so it is retired once.
+    unsafe fn retire_unlinked(&self, guard: &Guard<'_, Node<K, V>>, node: *mut Node<K, V>) {
+        if self.tracker.checks_reachability() {
+            assert!(!self.reachable(node), "Node {:?} with key {:?} retired while still linked", node, unsafe { &(*node).key });
+        }
+        unsafe { guard.retire(node) };
+    }
+
+    // Fails without touching the map if the tracker is over its memory budget
+    // and the budget policy is to report it
+    pub fn remove(&self, key: &K, token: &ThreadToken) -> Result<Option<V>, BudgetExceeded> {
+        let _scope = self.uaf_checks.then(|| OperationScope::enter("remove"));
+        self.tracker.admit(token)?;
+        let guard = self.tracker.pin(token);
+        let bucket = &self.buckets[self.hash(key)];
+        let mut backoff = self.backoff.start();
+
+        loop {
+            let (prev, cur, found) = self.find(&guard, bucket, key, "remove", token);
+            if !found {
+                return Ok(None);
+            }
+            let cur_node = unsafe { self.node(cur, "remove", token) };
+            let next = cur_node.next.load(Ordering::SeqCst);
+            // Logical deletion: marking next also stops inserts after cur. If it
+            // fails cur got a new successor or another remove won, look again.
+            if is_marked(next)
+                || cur_node.next.compare_exchange(next, marked(next), Ordering::SeqCst, Ordering::Relaxed).is_err() {
+                backoff.failed();
+                continue;
+            }
+            let value = cur_node.value.clone();
+            // Physical deletion, left to the next walk over cur if prev changed
+            if prev.compare_exchange(cur, next, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
+                // Our CAS unlinked cur
+                unsafe { self.retire_unlinked(&guard, cur) };
+            } else {
+                self.find(&guard, bucket, key, "remove", token);
+            }
+            return Ok(Some(value));
+        }
+    }
+
+    // fn load(&self) -> Vec<(K, V)> {
+    //     let mut result = Vec::new();
+    //     for bucket in &self.buckets {
+    //         let mut cur = bucket.load(Ordering::SeqCst);
+    //         while !cur.is_null() {
+    //             unsafe {
+    //                 let cur_node = &*cur;
+    //                 result.push((cur_node.key.clone(), cur_node.value.clone()));
+    //                 cur = cur_node.next.load(Ordering::SeqCst);
+    //             }
+    //         }
+    //     }
+    //     result
+    // }
+    //
+    #[allow(dead_code)]
+    fn print(&self) {
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            print!("Bucket {}: ", i);
+            let mut cur = bucket.load(Ordering::SeqCst);
+            while !cur.is_null() {
+                unsafe {
+                    let cur_node = &*cur;
+                    print!("({:?}, {:?}) -> ", cur_node.key, cur_node.value);
+                    cur = unmarked(cur_node.next.load(Ordering::SeqCst));
+                }
+            }
+            println!("null");
+        }
+    }
+}
+
+impl<K: Debug, V: Debug> SortedUnorderedMap<K, V> {
+    // Buckets and tracker state for a DOT or JSON export. The calling thread is
+    // inside an operation while `during` runs, so the retirement lists keep what
+    // the other threads retire meanwhile. The buckets are walked after it, once
+    // no other thread runs an operation on the map.
+    pub fn snapshot(&self, token: &ThreadToken, during: &mut dyn FnMut()) -> StateSnapshot {
+        let _guard = self.tracker.pin(token);
+        during();
+        let buckets = self.buckets.iter().map(|bucket| {
+            let mut nodes = Vec::new();
+            let mut cur = bucket.load(Ordering::SeqCst);
+            while !cur.is_null() {
+                let node = unsafe { &*cur };
+                let next = node.next.load(Ordering::SeqCst);
+                nodes.push(NodeState { addr: cur as usize, key: format!("{:?}", node.key), value: format!("{:?}", node.value),
+                                       marked: is_marked(next) });
+                cur = unmarked(next);
+            }
+            nodes
+        }).collect();
+        StateSnapshot { tracker: self.tracker_info(), retirement: self.tracker.retirement_state(token), buckets }
+    }
+}
+
+impl<K, V> SortedUnorderedMap<K, V> {
+    // Name of the reclamation scheme and the parameters it currently runs with
+    pub fn tracker_info(&self) -> String {
+        format!("{:?} ({})", self.tracker.tracker_type(), self.tracker.describe())
+    }
+
+    // Drop a node that no other thread can see and release its memory
+    unsafe fn free_node(&self, node: *mut Node<K, V>) {
+        self.tracker.reclaim(node);
+        self.freed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Free every node that is still linked or retired. Nodes are only
+    // freed when no thread is inside the tracker, otherwise they are left to leak and
+    // show up as outstanding in the report.
+    pub fn teardown(&mut self) -> LeakReport {
+        let active_threads = self.tracker.active_threads();
+        if active_threads == 0 {
+            for bucket in &self.buckets {
+                let mut cur = bucket.swap(null_mut(), Ordering::SeqCst);
+                while !cur.is_null() {
+                    unsafe {
+                        let next = unmarked((*cur).next.load(Ordering::SeqCst));
+                        self.free_node(cur);
+                        cur = next;
+                    }
+                }
+            }
+            self.tracker.drain();
+        }
+        let stats = self.tracker.report_retired();
+        LeakReport {
+            allocated: stats.allocated,
+            retired: stats.retired,
+            reclaimed: stats.reclaimed,
+            freed: self.freed.load(Ordering::Relaxed),
+            active_threads,
+        }
+    }
+}
+
+impl<K, V> RetiredMonitorable for SortedUnorderedMap<K, V> {
+    fn collect_retired_stats(&self) -> Vec<(usize, RetiredStats)> {
+        self.tracker.collect_retired_stats()
+    }
+}
+
+impl<K, V> ConcurrentMap<K, V> for SortedUnorderedMap<K, V>
+where
+    K: Ord + Hash + Clone + Debug + Send + Sync + 'static,
+    V: Clone + Debug + Send + Sync + 'static,
+{
+    fn register(&self) -> ThreadToken {
+        SortedUnorderedMap::register(self)
+    }
+
+    fn unregister(&self, token: ThreadToken) {
+        SortedUnorderedMap::unregister(self, token)
+    }
+
+    fn insert(&self, key: K, value: V, token: &ThreadToken) -> bool {
+        SortedUnorderedMap::insert(self, key, value, token)
+    }
+
+    fn get(&self, key: &K, token: &ThreadToken) -> Option<V> {
+        SortedUnorderedMap::get(self, key, token)
+    }
+
+    fn remove(&self, key: &K, token: &ThreadToken) -> Result<Option<V>, BudgetExceeded> {
+        SortedUnorderedMap::remove(self, key, token)
+    }
+
+    fn tracker_info(&self) -> String {
+        SortedUnorderedMap::tracker_info(self)
+    }
+
+    fn snapshot(&self, token: &ThreadToken, during: &mut dyn FnMut()) -> StateSnapshot {
+        SortedUnorderedMap::snapshot(self, token, during)
+    }
+
+    fn teardown(&mut self) -> LeakReport {
+        SortedUnorderedMap::teardown(self)
+    }
+}
+
+impl<K, V> Drop for SortedUnorderedMap<K, V> {
+    fn drop(&mut self) {
+        let report = self.teardown();
+        if report.outstanding() != 0 {
+            eprintln!("{}", report);
+        }
+    }
+}
+
+// fn testLinkList1Thread() {
+//     // 声明一个list
+//     let list = SortedUnorderedMap::new(1);
+//
+//     list.insert(10, "Ten");
+//     list.insert(20, "Twenty");
+//     list.insert(15, "Fifteen");
+//     list.insert(5, "Five");
+//     list.insert(25, "Twenty-Five");
+//
+//     println!("After insertion:");
+//     list.print();
+//
+//     println!("Get key 15: {:?}", list.get(&15));
+//     println!("Get key 30: {:?}", list.get(&30));
+//
+//     println!("Remove key 20: {:?}", list.remove(&20));
+//
+//     println!("After removal:");
+//     list.print();
+//     let all_elements = list.load();
+//     println!("All elements in the map: {:?}", all_elements);
+// }
\ No newline at end of file
@@ -0,0 +1,502 @@
+use std::cmp::Ordering as KeyOrdering;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::ptr::{null_mut, slice_from_raw_parts_mut};
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+use crate::structures::{is_marked, marked, unmarked, ConcurrentMap, LeakReport, CUR, NEXT, NODE_CANARY, PREV};
+use crate::trackers::MemoryTracker::{Guard, MemoryTracker, TrackerConfig};
+use crate::util::Backoff::BackoffStrategy;
+use crate::util::KeyHasher::HasherType;
+use crate::util::MemoryBudget::BudgetExceeded;
+use crate::util::Quarantine::{report_use_after_free, OperationScope};
+use crate::util::RetiredMonitorable::{RetiredMonitorable, RetiredStats};
+use crate::util::StateExport::{NodeState, StateSnapshot};
+use crate::util::ThreadRegistry::ThreadToken;
+
+// Split-ordered list (Shalev and Shavit): all nodes sit in one Harris-Michael
+// list sorted by their hash with the bits reversed, so the keys of bucket b,
+// for a table of any power-of-two size, follow each other in the list. Every
+// bucket but the first starts at a dummy node, and doubling the table only
+// links the dummy of a new bucket into the run of its parent, the bucket whose
+// index is b without its highest bit. No node ever moves.
+
+// Items per bucket above which the table doubles
+const MAX_LOAD: usize = 2;
+// Segment s holds buckets 2^s to 2^(s+1) - 1, bucket 0 is the list head itself
+const SEGMENTS: usize = usize::BITS as usize - 16;
+const MAX_BUCKETS: usize = 1 << SEGMENTS;
+
+// The highest bit of a hash becomes the lowest of a regular key, which sorts it
+// after the dummy of its bucket, whose lowest bit is clear
+fn regular_key(hash: u64) -> u64 {
+    (hash | 1 << 63).reverse_bits()
+}
+
+fn dummy_key(bucket: usize) -> u64 {
+    (bucket as u64).reverse_bits()
+}
+
+// The bucket a new bucket is split from
+fn parent(bucket: usize) -> usize {
+    bucket & !(1 << bucket.ilog2())
+}
+
+// Segment of a bucket other than 0 and its position in there
+fn segment_of(bucket: usize) -> (usize, usize) {
+    let segment = bucket.ilog2() as usize;
+    (segment, bucket - (1 << segment))
+}
+
+// Node struct, a dummy has no entry. Nodes are allocated by the tracker.
+pub(crate) struct Node<K, V> {
+    canary: u64, // NODE_CANARY while the node is alive, poisoned once it is freed in use-after-free mode
+    so_key: u64, // Split-order key, odd for regular nodes
+    pub(crate) entry: Option<(K, V)>,
+    pub(crate) next: AtomicPtr<Node<K, V>>,
+}
+
+impl<K: Ord, V> Node<K, V> {
+    fn new(so_key: u64, entry: Option<(K, V)>, next: *mut Node<K, V>) -> Node<K, V> {
+        Node { canary: NODE_CANARY, so_key, entry, next: AtomicPtr::new(next) }
+    }
+
+    // Order of the node against the position of (so_key, key), a dummy is
+    // looked up without a key
+    fn compare(&self, so_key: u64, key: Option<&K>) -> KeyOrdering {
+        self.so_key.cmp(&so_key).then_with(|| match (&self.entry, key) {
+            (Some((own, _)), Some(key)) => own.cmp(key),
+            _ => KeyOrdering::Equal,
+        })
+    }
+}
+
+// Hash map whose bucket table grows with the number of keys
+pub struct SplitOrderedMap<K, V> {
+    tracker: MemoryTracker<Node<K, V>>,
+    head: AtomicPtr<Node<K, V>>,  // Start of the list and of bucket 0
+    segments: [AtomicPtr<AtomicPtr<Node<K, V>>>; SEGMENTS], // Dummies of the buckets, allocated on first use
+    size: AtomicUsize,            // Buckets in use, a power of two
+    len: AtomicUsize,             // Keys in the map
+    freed: AtomicU64,             // Nodes freed without going through the tracker
+    backoff: BackoffStrategy,     // Wait between retries of a failed CAS on a link
+    uaf_checks: bool,             // Check the canary of every node before using it
+    hasher: HasherType,           // Hash whose low bits pick the bucket of a key
+}
+
+impl<K, V> SplitOrderedMap<K, V>
+where
+    K: Ord + Hash + Clone + Debug + Send + 'static,
+    V: Clone + Debug + Send + 'static,
+{
+    // `tracker_type` names the reclamation scheme, see trackers::MemoryTracker.
+    // Keys are hashed with SipHash.
+    pub fn new(initial_buckets: usize, tracker_type: &str, config: TrackerConfig) -> Self {
+        SplitOrderedMap::with_hasher(initial_buckets, HasherType::SipHash, tracker_type, config)
+    }
+
+    // The table starts with `initial_buckets` rounded up to a power of two
+    pub fn with_hasher(initial_buckets: usize, hasher: HasherType, tracker_type: &str, config: TrackerConfig) -> Self {
+        assert!(initial_buckets > 0 && initial_buckets <= MAX_BUCKETS, "A map needs between 1 and 2^{} buckets", SEGMENTS);
+        // find holds prev, cur and next at once
+        let config = TrackerConfig { slot_num: config.slot_num.max(NEXT + 1), ..config };
+        let backoff = config.backoff;
+        let uaf_checks = config.quarantine > 0;
+        SplitOrderedMap {
+            tracker: MemoryTracker::new(tracker_type, config),
+            head: AtomicPtr::new(null_mut()),
+            segments: std::array::from_fn(|_| AtomicPtr::new(null_mut())),
+            size: AtomicUsize::new(initial_buckets.next_power_of_two()),
+            len: AtomicUsize::new(0),
+            freed: AtomicU64::new(0),
+            backoff,
+            uaf_checks,
+            hasher,
+        }
+    }
+
+    // Claim a slot for the calling thread. The token is passed to every operation
+    // and handed back to `unregister` when the thread is done with the map.
+    pub fn register(&self) -> ThreadToken {
+        self.tracker.register()
+    }
+
+    pub fn unregister(&self, token: ThreadToken) {
+        self.tracker.unregister(token);
+    }
+
+    // Buckets of the table at this moment
+    pub fn bucket_count(&self) -> usize {
+        self.size.load(Ordering::SeqCst)
+    }
+
+    pub fn insert(&self, key: K, value: V, token: &ThreadToken) -> bool {
+        let _scope = self.uaf_checks.then(|| OperationScope::enter("insert"));
+        let guard = self.tracker.pin(token);
+        let hash = self.hasher.hash(&key);
+        let so_key = regular_key(hash);
+        let bucket = self.bucket(&guard, hash, token);
+        let mut value = Some(value);
+        // Allocated once the insertion point is found, kept across retries
+        let mut new_node: *mut Node<K, V> = null_mut();
+        let mut backoff = self.backoff.start();
+
+        loop {
+            let (prev, cur, found) = self.find(&guard, bucket, so_key, Some(&key), "insert", token);
+            if found {
+                // Another thread inserted the key while we were retrying
+                if !new_node.is_null() {
+                    unsafe { self.free_node(new_node) };
+                }
+                return false;
+            }
+            match value.take() {
+                Some(value) => new_node = self.tracker.alloc(Node::new(so_key, Some((key.clone(), value)), cur), token),
+                None => unsafe { (*new_node).next.store(cur, Ordering::Relaxed) },
+            }
+            // Fails if a node was linked after prev or prev itself was deleted
+            if prev.compare_exchange(cur, new_node, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
+                self.grow(self.len.fetch_add(1, Ordering::SeqCst) + 1);
+                return true;
+            }
+            backoff.failed();
+        }
+    }
+
+    // Double the table once the average bucket holds more than MAX_LOAD keys.
+    // The new buckets get their dummies when an operation first reaches them.
+    fn grow(&self, len: usize) {
+        let size = self.size.load(Ordering::SeqCst);
+        if len > size * MAX_LOAD && size < MAX_BUCKETS {
+            // Losing means another thread has just doubled it
+            let _ = self.size.compare_exchange(size, size * 2, Ordering::SeqCst, Ordering::Relaxed);
+        }
+    }
+
+    pub fn get(&self, key: &K, token: &ThreadToken) -> Option<V> {
+        let _scope = self.uaf_checks.then(|| OperationScope::enter("get"));
+        let guard = self.tracker.pin(token);
+        let hash = self.hasher.hash(key);
+        let bucket = self.bucket(&guard, hash, token);
+        let (_, cur, found) = self.find(&guard, bucket, regular_key(hash), Some(key), "get", token);
+        found.then(|| unsafe { self.node(cur, "get", token) }.entry.as_ref().unwrap().1.clone())
+    }
+
+    // Fails without touching the map if the tracker is over its memory budget
+    // and the budget policy is to report it
+    pub fn remove(&self, key: &K, token: &ThreadToken) -> Result<Option<V>, BudgetExceeded> {
+        let _scope = self.uaf_checks.then(|| OperationScope::enter("remove"));
+        self.tracker.admit(token)?;
+        let guard = self.tracker.pin(token);
+        let hash = self.hasher.hash(key);
+        let so_key = regular_key(hash);
+        let bucket = self.bucket(&guard, hash, token);
+        let mut backoff = self.backoff.start();
+
+        loop {
+            let (prev, cur, found) = self.find(&guard, bucket, so_key, Some(key), "remove", token);
+            if !found {
+                return Ok(None);
+            }
+            let cur_node = unsafe { self.node(cur, "remove", token) };
+            let next = cur_node.next.load(Ordering::SeqCst);
+            // Logical deletion: marking next also stops inserts after cur. If it
+            // fails cur got a new successor or another remove won, look again.
+            if is_marked(next)
+                || cur_node.next.compare_exchange(next, marked(next), Ordering::SeqCst, Ordering::Relaxed).is_err() {
+                backoff.failed();
+                continue;
+            }
+            let value = cur_node.entry.as_ref().unwrap().1.clone();
+            self.len.fetch_sub(1, Ordering::SeqCst);
+            // Physical deletion, left to the next walk over cur if prev changed
+            if prev.compare_exchange(cur, next, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
+                // Our CAS unlinked cur
+                unsafe { self.retire_unlinked(&guard, cur) };
+            } else {
+                self.find(&guard, bucket, so_key, Some(key), "remove", token);
+            }
+            return Ok(Some(value));
+        }
+    }
+
+    // The link a walk for `hash` starts from: the head for bucket 0, else the
+    // `next` of the bucket's dummy, which is never marked
+    fn bucket<'a>(&'a self, guard: &Guard<'_, Node<K, V>>, hash: u64, token: &ThreadToken) -> &'a AtomicPtr<Node<K, V>> {
+        let size = self.size.load(Ordering::SeqCst);
+        self.bucket_start(guard, (hash & (size as u64 - 1)) as usize, token)
+    }
+
+    fn bucket_start<'a>(&'a self, guard: &Guard<'_, Node<K, V>>, bucket: usize,
+                        token: &ThreadToken) -> &'a AtomicPtr<Node<K, V>> {
+        if bucket == 0 {
+            return &self.head;
+        }
+        let slot = self.slot(bucket);
+        let mut dummy = slot.load(Ordering::SeqCst);
+        if dummy.is_null() {
+            // The dummy goes into the run of the parent, which is set up first
+            let parent = self.bucket_start(guard, parent(bucket), token);
+            dummy = self.link_dummy(guard, parent, bucket, token);
+            // Threads racing here all found or linked the same dummy
+            let _ = slot.compare_exchange(null_mut(), dummy, Ordering::SeqCst, Ordering::Relaxed);
+        }
+        // Dummies are only freed by teardown
+        unsafe { &(*dummy).next }
+    }
+
+    // Link the dummy of `bucket` after `parent`, or return the one another
+    // thread already linked
+    fn link_dummy(&self, guard: &Guard<'_, Node<K, V>>, parent: &AtomicPtr<Node<K, V>>, bucket: usize,
+                  token: &ThreadToken) -> *mut Node<K, V> {
+        let so_key = dummy_key(bucket);
+        let mut new_node: *mut Node<K, V> = null_mut();
+        let mut backoff = self.backoff.start();
+        loop {
+            let (prev, cur, found) = self.find(guard, parent, so_key, None, "split", token);
+            if found {
+                if !new_node.is_null() {
+                    unsafe { self.free_node(new_node) };
+                }
+                return cur;
+            }
+            if new_node.is_null() {
+                new_node = self.tracker.alloc(Node::new(so_key, None, cur), token);
+            } else {
+                unsafe { (*new_node).next.store(cur, Ordering::Relaxed) };
+            }
+            if prev.compare_exchange(cur, new_node, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
+                return new_node;
+            }
+            backoff.failed();
+        }
+    }
+
+    // Where the dummy of `bucket` is kept, its segment allocated by the first
+    // thread that needs it
+    fn slot(&self, bucket: usize) -> &AtomicPtr<Node<K, V>> {
+        let (segment, offset) = segment_of(bucket);
+        let mut table = self.segments[segment].load(Ordering::SeqCst);
+        if table.is_null() {
+            let fresh: Box<[AtomicPtr<Node<K, V>>]> = (0..1usize << segment).map(|_| AtomicPtr::new(null_mut())).collect();
+            let fresh = Box::into_raw(fresh) as *mut AtomicPtr<Node<K, V>>;
+            table = match self.segments[segment].compare_exchange(null_mut(), fresh, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => fresh,
+                Err(current) => {
+                    unsafe { drop(Box::from_raw(slice_from_raw_parts_mut(fresh, 1 << segment))) };
+                    current
+                }
+            };
+        }
+        unsafe { &*table.add(offset) }
+    }
+
+    // Dereference a node read during `op`. In use-after-free mode a node whose
+    // canary is gone, i.e. one that was freed and poisoned, aborts the run.
+    unsafe fn node<'a>(&self, ptr: *mut Node<K, V>, op: &str, token: &ThreadToken) -> &'a Node<K, V> {
+        if self.uaf_checks && (*ptr).canary != NODE_CANARY {
+            report_use_after_free(ptr as *const u8, &format!("{} on slot {}", op, token.index()));
+        }
+        &*ptr
+    }
+
+    // Walk from `start` to the first node not below (so_key, key). Returns the
+    // link that points to it, the node (null at the end of the list) and whether
+    // it is the one looked for; the node is protected in CUR and the owner of the
+    // link in PREV. Marked nodes on the way are unlinked and retired by whoever
+    // unlinks them, as in SortedUnorderedMap::find.
+    fn find<'a>(&'a self, guard: &Guard<'_, Node<K, V>>, start: &'a AtomicPtr<Node<K, V>>, so_key: u64, key: Option<&K>,
+                op: &str, token: &ThreadToken) -> (&'a AtomicPtr<Node<K, V>>, *mut Node<K, V>, bool) {
+        let mut backoff = self.backoff.start();
+        'retry: loop {
+            let mut prev = start;
+            let mut cur = guard.read(prev, CUR); // The head and dummies are never marked
+            loop {
+                if cur.is_null() {
+                    return (prev, cur, false);
+                }
+                let cur_node = unsafe { self.node(cur, op, token) };
+                let next = guard.read(&cur_node.next, NEXT);
+                // cur must still be linked from prev, else next may already be gone
+                if prev.load(Ordering::SeqCst) != cur {
+                    backoff.failed();
+                    continue 'retry;
+                }
+                if is_marked(next) {
+                    // cur is deleted, help unlink it
+                    let next = unmarked(next);
+                    if prev.compare_exchange(cur, next, Ordering::SeqCst, Ordering::Relaxed).is_err() {
+                        backoff.failed();
+                        continue 'retry;
+                    }
+                    // Our CAS unlinked cur
+                    unsafe { self.retire_unlinked(guard, cur) };
+                    guard.transfer(NEXT, CUR);
+                    cur = next;
+                    continue;
+                }
+                let order = cur_node.compare(so_key, key);
+                if order != KeyOrdering::Less {
+                    return (prev, cur, order == KeyOrdering::Equal);
+                }
+                // cur becomes prev and next becomes cur, both stay protected
+                guard.transfer(CUR, PREV);
+                guard.transfer(NEXT, CUR);
+                prev = &cur_node.next;
+                cur = next;
+            }
+        }
+    }
+
+    // Whether `node` is linked in the list. Only meaningful while no other
+    // thread changes the map.
+    fn reachable(&self, node: *mut Node<K, V>) -> bool {
+        let mut cur = self.head.load(Ordering::SeqCst);
+        while !cur.is_null() {
+            if cur == node {
+                return true;
+            }
+            cur = unmarked(unsafe { (*cur).next.load(Ordering::SeqCst) });
+        }
+        false
+    }
+
+    // Hand a node to the tracker right after this thread unlinked it. Only the
+    // thread whose CAS unlinked `node` may call this, so it is retired once.
+    unsafe fn retire_unlinked(&self, guard: &Guard<'_, Node<K, V>>, node: *mut Node<K, V>) {
+        if self.tracker.checks_reachability() {
+            assert!(!self.reachable(node), "Node {:?} with entry {:?} retired while still linked", node, unsafe { &(*node).entry });
+        }
+        unsafe { guard.retire(node) };
+    }
+}
+
+impl<K: Debug, V: Debug> SplitOrderedMap<K, V> {
+    // The list and tracker state for a DOT or JSON export, as a single chain in
+    // which dummies show up as "bucket N". As for the bucket map, the caller is
+    // inside an operation while `during` runs and the list is walked after it.
+    pub fn snapshot(&self, token: &ThreadToken, during: &mut dyn FnMut()) -> StateSnapshot {
+        let _guard = self.tracker.pin(token);
+        during();
+        let mut nodes = Vec::new();
+        let mut cur = self.head.load(Ordering::SeqCst);
+        while !cur.is_null() {
+            let node = unsafe { &*cur };
+            let (key, value) = match &node.entry {
+                Some((key, value)) => (format!("{:?}", key), format!("{:?}", value)),
+                None => (format!("bucket {}", node.so_key.reverse_bits()), String::new()),
+            };
+            let next = node.next.load(Ordering::SeqCst);
+            nodes.push(NodeState { addr: cur as usize, key, value, marked: is_marked(next) });
+            cur = unmarked(next);
+        }
+        StateSnapshot { tracker: self.tracker_info(), retirement: self.tracker.retirement_state(token),
+                        buckets: vec![nodes] }
+    }
+}
+
+impl<K, V> SplitOrderedMap<K, V> {
+    // Name of the reclamation scheme and the parameters it currently runs with
+    pub fn tracker_info(&self) -> String {
+        format!("{:?} ({})", self.tracker.tracker_type(), self.tracker.describe())
+    }
+
+    // Drop a node that no other thread can see and release its memory
+    unsafe fn free_node(&self, node: *mut Node<K, V>) {
+        self.tracker.reclaim(node);
+        self.freed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Free every node that is still linked, dummies included, or retired. Nodes
+    // are only freed when no thread is inside the tracker, otherwise they are
+    // left to leak and show up as outstanding in the report.
+    pub fn teardown(&mut self) -> LeakReport {
+        let active_threads = self.tracker.active_threads();
+        if active_threads == 0 {
+            let mut cur = self.head.swap(null_mut(), Ordering::SeqCst);
+            while !cur.is_null() {
+                unsafe {
+                    let next = unmarked((*cur).next.load(Ordering::SeqCst));
+                    self.free_node(cur);
+                    cur = next;
+                }
+            }
+            // The freed dummies are linked again when their buckets are next used
+            for (segment, table) in self.segments.iter().enumerate() {
+                let table = table.load(Ordering::SeqCst);
+                for offset in 0..if table.is_null() { 0 } else { 1 << segment } {
+                    unsafe { (*table.add(offset)).store(null_mut(), Ordering::SeqCst) };
+                }
+            }
+            self.len.store(0, Ordering::SeqCst);
+            self.tracker.drain();
+        }
+        let stats = self.tracker.report_retired();
+        LeakReport {
+            allocated: stats.allocated,
+            retired: stats.retired,
+            reclaimed: stats.reclaimed,
+            freed: self.freed.load(Ordering::Relaxed),
+            active_threads,
+        }
+    }
+}
+
+impl<K, V> RetiredMonitorable for SplitOrderedMap<K, V> {
+    fn collect_retired_stats(&self) -> Vec<(usize, RetiredStats)> {
+        self.tracker.collect_retired_stats()
+    }
+}
+
+impl<K, V> ConcurrentMap<K, V> for SplitOrderedMap<K, V>
+where
+    K: Ord + Hash + Clone + Debug + Send + Sync + 'static,
+    V: Clone + Debug + Send + Sync + 'static,
+{
+    fn register(&self) -> ThreadToken {
+        SplitOrderedMap::register(self)
+    }
+
+    fn unregister(&self, token: ThreadToken) {
+        SplitOrderedMap::unregister(self, token)
+    }
+
+    fn insert(&self, key: K, value: V, token: &ThreadToken) -> bool {
+        SplitOrderedMap::insert(self, key, value, token)
+    }
+
+    fn get(&self, key: &K, token: &ThreadToken) -> Option<V> {
+        SplitOrderedMap::get(self, key, token)
+    }
+
+    fn remove(&self, key: &K, token: &ThreadToken) -> Result<Option<V>, BudgetExceeded> {
+        SplitOrderedMap::remove(self, key, token)
+    }
+
+    fn tracker_info(&self) -> String {
+        SplitOrderedMap::tracker_info(self)
+    }
+
+    fn snapshot(&self, token: &ThreadToken, during: &mut dyn FnMut()) -> StateSnapshot {
+        SplitOrderedMap::snapshot(self, token, during)
+    }
+
+    fn teardown(&mut self) -> LeakReport {
+        SplitOrderedMap::teardown(self)
+    }
+}
+
+impl<K, V> Drop for SplitOrderedMap<K, V> {
+    fn drop(&mut self) {
+        let report = self.teardown();
+        if report.outstanding() != 0 {
+            eprintln!("{}", report);
+        }
+        for (segment, table) in self.segments.iter().enumerate() {
+            let table = table.load(Ordering::SeqCst);
+            if !table.is_null() {
+                unsafe { drop(Box::from_raw(slice_from_raw_parts_mut(table, 1 << segment))) };
+            }
+        }
+    }
+}
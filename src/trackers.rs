@@ -0,0 +1,24 @@
+// Reclamation schemes, selected by name through the MemoryTracker dispatcher.
+// Each scheme is only built with its cargo feature.
+#[cfg(not(any(feature = "hyaline", feature = "interval", feature = "hazard")))]
+compile_error!("enable at least one tracker feature: hyaline, interval or hazard");
+#[allow(non_snake_case)]
+pub(crate) mod BaseTracker;
+#[cfg(feature = "hazard")]
+#[allow(non_snake_case)]
+pub(crate) mod HazardTracker;
+#[cfg(feature = "interval")]
+#[allow(non_snake_case)]
+pub(crate) mod IntervalTracker;
+#[allow(non_snake_case)]
+pub(crate) mod MemoryTracker;
+#[cfg(any(feature = "interval", feature = "hazard"))]
+#[allow(non_snake_case)]
+pub(crate) mod OrphanList;
+#[allow(non_snake_case)]
+pub(crate) mod RetireChecker;
+#[cfg(feature = "hyaline")]
+pub(crate) mod hyaline_alg;
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod Conformance;
@@ -1,21 +1,126 @@
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::ptr;
+use std::alloc::Layout;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use crate::util::MemoryBudget::BudgetExceeded;
+use crate::util::Quarantine::SystemRelease;
+use crate::util::RetiredMonitorable::RetiredMonitorable;
+#[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+use crate::util::StateExport::RetirementState;
+use crate::util::ThreadRegistry::ThreadToken;
 
+// Interface of a reclamation scheme. Objects are allocated through the tracker,
+// so every scheme can keep its bookkeeping (header, birth epoch, ...) next to
+// the object without the data structure knowing about it. Threads register
+// once and pass their token to every call.
+pub(crate) trait BaseTracker<T>: RetiredMonitorable + Send + Sync {
+    fn register(&self) -> ThreadToken;
+    fn unregister(&self, token: ThreadToken);
+    // Panics unless `token` came from `register` of this tracker
+    fn check_token(&self, token: &ThreadToken);
 
-trait BaseTracker<T> {
-    fn new(task_num: usize) -> Self;
-    fn get_retired_cnt(&self, tid: usize) -> u64;
-    fn inc_retired(&self, tid: usize);
-    fn dec_retired(&self, tid: usize);
-    fn alloc(&self) -> *mut T;
+    // Move `obj` into memory managed by the tracker
+    fn alloc(&self, obj: T, token: &ThreadToken) -> *mut T;
+    // Free an object that no other thread can reach, e.g. one never published
     fn reclaim(&self, obj: *mut T);
-    fn start_op(&self, tid: usize);
-    fn end_op(&self, tid: usize);
-    fn read(&self, obj: &AtomicU64, tid: usize) -> T;
-    fn transfer(&self, src_idx: usize, dst_idx: usize, tid: usize);
-    fn reserve(&self, obj: *mut T, idx: usize, tid: usize);
-    fn release(&self, idx: usize, tid: usize);
-    fn clear_all(&self, tid: usize);
-    fn retire(&self, obj: *mut T, tid: usize);
+
+    // Every pointer read between start_op and end_op is protected
+    fn start_op(&self, token: &ThreadToken);
+    // Ends the operation and drops every reservation it made
+    fn end_op(&self, token: &ThreadToken);
+
+    // Slot-based protection. `read` loads a pointer and protects it in slot `idx`
+    // until the slot is reused, released or cleared. `idx` is a physical slot, the
+    // dispatcher renames logical slots on top, see MemoryTracker::transfer. Schemes
+    // that protect everything between start_op and end_op keep the defaults.
+    fn read(&self, obj: &AtomicPtr<T>, _idx: usize, _token: &ThreadToken) -> *mut T {
+        obj.load(Ordering::Acquire)
+    }
+    fn reserve(&self, _obj: *mut T, _idx: usize, _token: &ThreadToken) {}
+    fn release(&self, _idx: usize, _token: &ThreadToken) {}
+    fn clear_all(&self, _token: &ThreadToken) {}
+
+    // Hand over an unlinked object, it is freed once no thread can reach it
+    fn retire(&self, obj: *mut T, token: &ThreadToken);
+    // Hand over something the tracker did not allocate: an object of another type
+    // or a closure. It is destroyed once every thread that is inside an operation
+    // now has left it. The caller must be inside an operation itself.
+    fn retire_foreign(&self, foreign: Foreign, token: &ThreadToken);
+    // Objects retired by this thread that are not freed yet
+    fn get_retired_cnt(&self, token: &ThreadToken) -> u64;
+
+    // Called outside of start_op/end_op, applies the memory budget of the tracker
+    // if it has one: before an operation that may retire, and by the dispatcher
+    // after an operation whose retires went over the budget
+    fn admit(&self, _token: &ThreadToken) -> Result<(), BudgetExceeded> {
+        Ok(())
+    }
+    // Whether the garbage of the tracker is at or over its memory budget
+    fn over_budget(&self) -> bool {
+        false
+    }
+
+    // Number of threads currently between start_op and end_op
+    fn active_threads(&self) -> i64;
+    // Free everything still retired once no thread is active
+    fn drain(&mut self);
+    // Parameters the tracker runs with, for the results
+    fn describe(&self) -> String;
+    // Retirement lists for a debugging snapshot, taken by a thread inside an
+    // operation. Schemes without a shared retirement list have none.
+    #[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+    fn retirement_state(&self, _token: &ThreadToken) -> Option<RetirementState> {
+        None
+    }
+}
+
+// An object of another type than the tracker's, allocated with Box, or a
+// deferred closure. Schemes only keep the pointer and how to destroy it, so one
+// tracker can protect a structure with nodes of several types.
+pub(crate) struct Foreign {
+    ptr: *mut u8,
+    layout: Layout,
+    destroy: unsafe fn(*mut u8), // Drops the value in place, the memory is released after
+}
+
+impl Foreign {
+    // `obj` must come from Box::into_raw
+    pub(crate) fn boxed<U: Send>(obj: *mut U) -> Foreign {
+        Foreign { ptr: obj as *mut u8, layout: Layout::new::<U>(), destroy: Foreign::drop_value::<U> }
+    }
+
+    pub(crate) fn closure(f: Box<dyn FnOnce() + Send>) -> Foreign {
+        let ptr = Box::into_raw(Box::new(f)) as *mut u8;
+        Foreign { ptr, layout: Layout::new::<Box<dyn FnOnce() + Send>>(), destroy: Foreign::run_closure }
+    }
+
+    unsafe fn drop_value<U>(ptr: *mut u8) {
+        std::ptr::drop_in_place(ptr as *mut U);
+    }
+
+    unsafe fn run_closure(ptr: *mut u8) {
+        let f = std::ptr::read(ptr as *mut Box<dyn FnOnce() + Send>);
+        f();
+    }
+
+    // Bytes the entry holds until it is freed
+    pub(crate) fn size(&self) -> u64 {
+        self.layout.size() as u64
+    }
+
+    // Destroy the value and hand its memory to `release`, returns the bytes released.
+    // Only called once per entry.
+    pub(crate) unsafe fn free(&self, release: &SystemRelease) -> u64 {
+        (self.destroy)(self.ptr);
+        release.release(self.ptr, self.layout);
+        self.size()
+    }
+}
+
+// Only objects and closures that are Send are accepted
+unsafe impl Send for Foreign {}
+
+// Entry of a per-thread retired list
+#[cfg(any(feature = "interval", feature = "hazard"))]
+pub(crate) enum Retiree<T> {
+    Object(*mut T), // Allocated by the tracker
+    Foreign(Foreign),
 }
@@ -0,0 +1,563 @@
+// Properties every reclamation scheme has to provide. Each test runs against
+// every TrackerType the dispatcher can build, with every allocator, so a new
+// scheme is covered as soon as it is listed in TrackerType::ALL and its
+// feature is enabled.
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::Duration;
+use crate::trackers::BaseTracker::{BaseTracker, Foreign};
+use crate::trackers::MemoryTracker::{MemoryTracker, TrackerConfig, TrackerType, TrackerTypeError};
+use crate::util::MemoryBudget::{BudgetPolicy, MemoryBudget};
+use crate::util::RAllocator::AllocatorType;
+use crate::util::ThreadRegistry::ThreadToken;
+
+const ALLOCATORS: [AllocatorType; 3] = [AllocatorType::System, AllocatorType::Pool, AllocatorType::Arena];
+// Without and with use-after-free mode, freed blocks are poisoned in the latter
+const QUARANTINES: [usize; 2] = [0, 64];
+// Retires after the unlink of a protected node, enough for every scheme to scan
+const CHURN: usize = 256;
+const THREADS: usize = 4;
+const OPS_PER_THREAD: usize = 2000;
+const SHARED_SLOTS: usize = 8;
+
+// Records every node the tracker frees
+#[derive(Default)]
+struct FreeLog {
+    next_id: AtomicUsize,
+    freed: Mutex<HashSet<usize>>,
+}
+
+impl FreeLog {
+    fn node(self: &Arc<Self>) -> TestNode {
+        TestNode { id: self.next_id.fetch_add(1, Ordering::Relaxed), log: Arc::clone(self) }
+    }
+
+    fn is_freed(&self, id: usize) -> bool {
+        self.freed.lock().unwrap().contains(&id)
+    }
+
+    fn freed_count(&self) -> usize {
+        self.freed.lock().unwrap().len()
+    }
+}
+
+struct TestNode {
+    id: usize,
+    log: Arc<FreeLog>,
+}
+
+impl Drop for TestNode {
+    fn drop(&mut self) {
+        let first = self.log.freed.lock().unwrap().insert(self.id);
+        assert!(first, "node {} freed twice", self.id);
+    }
+}
+
+// Scans on every retire and advances the epoch on every allocation, so a
+// scheme that frees too early does so within the test
+fn eager_config(allocator: AllocatorType, quarantine: usize) -> TrackerConfig {
+    TrackerConfig { epoch_freq: 1, empty_freq: 1, batch_size: 1, allocator, quarantine, ..TrackerConfig::default() }
+}
+
+// Run `check` on a fresh tracker of every type, allocator and quarantine
+fn for_each_tracker(check: impl Fn(&mut Box<dyn BaseTracker<TestNode>>, &Arc<FreeLog>, &str)) {
+    for tracker_type in TrackerType::enabled() {
+        for allocator in ALLOCATORS {
+            for quarantine in QUARANTINES {
+                let label = format!("{:?} with {:?} allocator, quarantine {}", tracker_type, allocator, quarantine);
+                let log = Arc::new(FreeLog::default());
+                let mut tracker = MemoryTracker::<TestNode>::build(tracker_type, eager_config(allocator, quarantine));
+                check(&mut tracker, &log, &label);
+                drop(tracker);
+                assert_eq!(log.freed_count(), log.next_id.load(Ordering::Relaxed), "{}: nodes leaked", label);
+            }
+        }
+    }
+}
+
+// Unlink the node in `shared` and retire it, then keep retiring fresh nodes
+fn unlink_and_churn(tracker: &dyn BaseTracker<TestNode>, log: &Arc<FreeLog>, shared: &AtomicPtr<TestNode>,
+                    token: &ThreadToken) {
+    tracker.start_op(token);
+    let old = tracker.read(shared, 0, token);
+    shared.store(tracker.alloc(log.node(), token), Ordering::SeqCst);
+    tracker.retire(old, token);
+    tracker.end_op(token);
+    for _ in 0..CHURN {
+        tracker.start_op(token);
+        let node = tracker.alloc(log.node(), token);
+        tracker.retire(node, token);
+        tracker.end_op(token);
+    }
+}
+
+// A reader protects the node in `shared` while a writer unlinks and retires it.
+// The node must survive until the reader leaves and be freed once everybody has.
+fn check_protected_node(tracker: &mut Box<dyn BaseTracker<TestNode>>, log: &Arc<FreeLog>, label: &str) {
+    let reader = tracker.register();
+    let writer = tracker.register();
+    let shared = AtomicPtr::new(tracker.alloc(log.node(), &writer));
+
+    tracker.start_op(&reader);
+    let protected = tracker.read(&shared, 0, &reader);
+    let id = unsafe { (*protected).id };
+    unlink_and_churn(tracker.as_ref(), log, &shared, &writer);
+    assert!(!log.is_freed(id), "{}: node freed while a reader holds it", label);
+    tracker.end_op(&reader);
+
+    tracker.unregister(reader);
+    tracker.unregister(writer);
+    assert_eq!(tracker.active_threads(), 0, "{}: threads still active", label);
+    tracker.drain();
+    assert!(log.is_freed(id), "{}: retired node not freed after every thread left", label);
+    tracker.reclaim(shared.load(Ordering::SeqCst));
+}
+
+#[test]
+fn protected_node_is_never_freed() {
+    for_each_tracker(check_protected_node);
+}
+
+// A writer retires a node a reader protects and exits with it still on its
+// retired list. Another thread's scans adopt the orphan: it survives them while
+// the reader holds it and is freed exactly once after the reader leaves.
+#[test]
+fn orphaned_node_survives_until_released() {
+    for_each_tracker(|tracker, log, label| {
+        let reader = tracker.register();
+        let survivor = tracker.register();
+        let writer = tracker.register();
+        let shared = AtomicPtr::new(tracker.alloc(log.node(), &writer));
+
+        tracker.start_op(&reader);
+        let protected = tracker.read(&shared, 0, &reader);
+        let id = unsafe { (*protected).id };
+        unlink_and_churn(tracker.as_ref(), log, &shared, &writer);
+        tracker.unregister(writer);
+        unlink_and_churn(tracker.as_ref(), log, &shared, &survivor);
+        assert!(!log.is_freed(id), "{}: orphaned node freed while a reader holds it", label);
+
+        tracker.end_op(&reader);
+        unlink_and_churn(tracker.as_ref(), log, &shared, &survivor);
+        assert!(log.is_freed(id), "{}: orphaned node not freed by the survivor after the reader left", label);
+
+        tracker.unregister(reader);
+        tracker.unregister(survivor);
+        tracker.drain();
+        tracker.reclaim(shared.load(Ordering::SeqCst));
+    });
+}
+
+#[test]
+fn double_end_op_is_harmless() {
+    for_each_tracker(|tracker, log, label| {
+        let token = tracker.register();
+        tracker.start_op(&token);
+        tracker.end_op(&token);
+        tracker.end_op(&token);
+        assert_eq!(tracker.active_threads(), 0, "{}: second end_op changed the active count", label);
+        tracker.unregister(token);
+        // The tracker must still protect readers afterwards
+        check_protected_node(tracker, log, label);
+    });
+}
+
+// Threads keep replacing the nodes of a few shared slots and retiring the old
+// ones. Every retired node must be freed once they have all left, and no node
+// may be freed twice (TestNode panics on the second drop).
+#[test]
+fn retired_nodes_are_freed_once_after_threads_leave() {
+    for_each_tracker(|tracker, log, label| {
+        let setup = tracker.register();
+        let shared: Vec<AtomicPtr<TestNode>> =
+            (0..SHARED_SLOTS).map(|_| AtomicPtr::new(tracker.alloc(log.node(), &setup))).collect();
+        tracker.unregister(setup);
+        let retired = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            for t in 0..THREADS {
+                let (tracker, shared, retired) = (&**tracker, &shared, &retired);
+                scope.spawn(move || {
+                    let token = tracker.register();
+                    let mut own = Vec::new();
+                    for i in 0..OPS_PER_THREAD {
+                        let slot = &shared[(t + i) % SHARED_SLOTS];
+                        tracker.start_op(&token);
+                        let old = tracker.read(slot, 0, &token);
+                        let new = tracker.alloc(log.node(), &token);
+                        if slot.compare_exchange(old, new, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+                            own.push(unsafe { (*old).id });
+                            tracker.retire(old, &token);
+                        } else {
+                            tracker.reclaim(new);
+                        }
+                        tracker.end_op(&token);
+                    }
+                    tracker.unregister(token);
+                    retired.lock().unwrap().append(&mut own);
+                });
+            }
+        });
+
+        assert_eq!(tracker.active_threads(), 0, "{}: threads still active", label);
+        tracker.drain();
+        for id in retired.into_inner().unwrap() {
+            assert!(log.is_freed(id), "{}: retired node {} not freed", label, id);
+        }
+        for slot in &shared {
+            tracker.reclaim(slot.load(Ordering::SeqCst));
+        }
+    });
+}
+
+// Checking mode of the dispatcher: a second retire of a node panics before the
+// scheme sees it, a node whose block was recycled can be retired again
+#[test]
+fn checking_mode_catches_double_retire() {
+    for tracker_type in TrackerType::enabled() {
+        let log = Arc::new(FreeLog::default());
+        let config = TrackerConfig { check_retire: true, ..eager_config(AllocatorType::Pool, 0) };
+        let tracker = MemoryTracker::<TestNode>::new(&format!("{:?}", tracker_type), config);
+        let token = tracker.register();
+
+        tracker.start_op(&token);
+        let node = tracker.alloc(log.node(), &token);
+        unsafe { tracker.retire(node, &token) };
+        let second = panic::catch_unwind(AssertUnwindSafe(|| unsafe { tracker.retire(node, &token) }));
+        assert!(second.is_err(), "{:?}: double retire accepted", tracker_type);
+        tracker.end_op(&token).unwrap();
+
+        // The pool hands the freed block out again, as a new node
+        churn(&tracker, &log, &token);
+        tracker.unregister(token);
+    }
+}
+
+// Retire and free nodes until the dispatcher has handed out recycled blocks
+fn churn(tracker: &MemoryTracker<TestNode>, log: &Arc<FreeLog>, token: &ThreadToken) {
+    for _ in 0..CHURN {
+        tracker.start_op(token);
+        let node = tracker.alloc(log.node(), token);
+        unsafe { tracker.retire(node, token) };
+        tracker.end_op(token).unwrap();
+    }
+}
+
+// What a guard reads stays protected until the guard is dropped, which ends the
+// operation
+#[test]
+fn guard_protects_until_dropped() {
+    for tracker_type in TrackerType::enabled() {
+        let log = Arc::new(FreeLog::default());
+        let config = eager_config(AllocatorType::System, 0);
+        let mut tracker = MemoryTracker::<TestNode>::new(&format!("{:?}", tracker_type), config);
+        let reader = tracker.register();
+        let writer = tracker.register();
+        let shared = AtomicPtr::new(tracker.alloc(log.node(), &writer));
+
+        let guard = tracker.pin(&reader);
+        let id = unsafe { (*guard.read(&shared, 0)).id };
+        {
+            let writing = tracker.pin(&writer);
+            let old = writing.read(&shared, 0);
+            shared.store(tracker.alloc(log.node(), &writer), Ordering::SeqCst);
+            unsafe { writing.retire(old) };
+        }
+        churn(&tracker, &log, &writer);
+        assert!(!log.is_freed(id), "{:?}: node freed while a guard holds it", tracker_type);
+        drop(guard);
+        assert_eq!(tracker.active_threads(), 0, "{:?}: dropped guard left the thread active", tracker_type);
+
+        tracker.unregister(reader);
+        tracker.unregister(writer);
+        tracker.drain();
+        assert!(log.is_freed(id), "{:?}: retired node not freed after the guard was dropped", tracker_type);
+        unsafe { tracker.reclaim(shared.load(Ordering::SeqCst)) };
+    }
+}
+
+// One tracker retires objects of two other types and a closure while a reader
+// is inside an operation. None of them is destroyed before the reader leaves,
+// each exactly once after (TestNode panics on a second drop).
+#[test]
+fn foreign_objects_and_closures_are_destroyed_once() {
+    for tracker_type in TrackerType::enabled() {
+        for quarantine in QUARANTINES {
+            let label = format!("{:?}, quarantine {}", tracker_type, quarantine);
+            let log = Arc::new(FreeLog::default());
+            let config = eager_config(AllocatorType::System, quarantine);
+            let mut tracker = MemoryTracker::<TestNode>::new(&format!("{:?}", tracker_type), config);
+            let reader = tracker.register();
+            let writer = tracker.register();
+
+            let guard = tracker.pin(&reader);
+            let (leaf, pair, captured) = (log.node(), log.node(), log.node());
+            let ids = [leaf.id, pair.id, captured.id];
+            // Other threads keep working while the reader stays, epochs move on
+            churn(&tracker, &log, &writer);
+            {
+                let writing = tracker.pin(&writer);
+                unsafe {
+                    writing.retire_boxed(Box::into_raw(Box::new(vec![leaf])));
+                    writing.retire_boxed(Box::into_raw(Box::new((pair, 7u64))));
+                }
+                writing.defer(move || drop(captured));
+            }
+            churn(&tracker, &log, &writer);
+            for id in ids {
+                assert!(!log.is_freed(id), "{}: foreign entry {} destroyed while a reader is active", label, id);
+            }
+            drop(guard);
+
+            let outside = panic::catch_unwind(AssertUnwindSafe(|| tracker.defer(|| {}, &writer)));
+            assert!(outside.is_err(), "{}: defer accepted outside of an operation", label);
+            tracker.unregister(reader);
+            tracker.unregister(writer);
+            tracker.drain();
+            for id in ids {
+                assert!(log.is_freed(id), "{}: foreign entry {} not destroyed after the reader left", label, id);
+            }
+            drop(tracker);
+            assert_eq!(log.freed_count(), log.next_id.load(Ordering::Relaxed), "{}: nodes leaked", label);
+        }
+    }
+}
+
+// A token is only accepted by the tracker that issued it, also when its slot
+// lies beyond every slot the other tracker has allocated
+#[test]
+fn foreign_token_is_refused() {
+    for tracker_type in TrackerType::enabled() {
+        let name = format!("{:?}", tracker_type);
+        let issuer = MemoryTracker::<TestNode>::new(&name, eager_config(AllocatorType::System, 0));
+        let other = MemoryTracker::<TestNode>::new(&name, eager_config(AllocatorType::System, 0));
+        let tokens: Vec<ThreadToken> = (0..70).map(|_| issuer.register()).collect();
+        for token in [&tokens[0], &tokens[69]] {
+            let start = panic::catch_unwind(AssertUnwindSafe(|| other.start_op(token)));
+            assert!(start.is_err(), "{:?}: start_op accepted token {} of another tracker", tracker_type, token.index());
+            let transfer = panic::catch_unwind(AssertUnwindSafe(|| other.transfer(0, 1, token)));
+            assert!(transfer.is_err(), "{:?}: transfer accepted token {} of another tracker", tracker_type, token.index());
+        }
+        assert_eq!(other.active_threads(), 0, "{:?}: foreign token left the tracker active", tracker_type);
+        for token in tokens {
+            issuer.unregister(token);
+        }
+    }
+}
+
+// Nodes the budget of `budget_cap_holds_under_every_policy` leaves room for
+const BUDGET_NODES: usize = 32;
+
+// A writer retires through guards while a reader stalls inside an operation,
+// until the writer makes no more progress. The garbage must stay under the
+// budget: Block and SpinHelp wait at the end of the operation that went over
+// it, Error refuses the next operation in `admit`. Every block is at least as
+// large as its node, so the cap in bytes bounds the retired but unfreed nodes.
+#[test]
+fn budget_cap_holds_under_every_policy() {
+    for policy in [BudgetPolicy::Block, BudgetPolicy::SpinHelp, BudgetPolicy::Error] {
+        for tracker_type in TrackerType::enabled() {
+            let label = format!("{:?} with {:?} policy", tracker_type, policy);
+            let log = Arc::new(FreeLog::default());
+            let budget = MemoryBudget::new((BUDGET_NODES * size_of::<TestNode>()) as u64, policy);
+            let config = TrackerConfig { budget: Some(budget), ..TrackerConfig::default() };
+            let tracker = MemoryTracker::<TestNode>::new(&format!("{:?}", tracker_type), config);
+            let reader = tracker.register();
+            let progress = AtomicUsize::new(0);
+
+            let guard = tracker.pin(&reader);
+            let most_unfreed = thread::scope(|scope| {
+                let (tracker, log, progress) = (&tracker, &log, &progress);
+                let writer = scope.spawn(move || {
+                    let token = tracker.register();
+                    let mut most_unfreed = 0;
+                    for retired in 1..=OPS_PER_THREAD {
+                        while policy == BudgetPolicy::Error && tracker.admit(&token).is_err() {
+                            thread::yield_now();
+                        }
+                        {
+                            let writing = tracker.pin(&token);
+                            let node = tracker.alloc(log.node(), &token);
+                            unsafe { writing.retire(node) };
+                        }
+                        most_unfreed = most_unfreed.max(retired - log.freed_count());
+                        progress.store(retired, Ordering::SeqCst);
+                    }
+                    tracker.unregister(token);
+                    most_unfreed
+                });
+                let mut seen = 0;
+                loop {
+                    thread::sleep(Duration::from_millis(20));
+                    let now = progress.load(Ordering::SeqCst);
+                    if now == seen || now == OPS_PER_THREAD {
+                        break;
+                    }
+                    seen = now;
+                }
+                drop(guard);
+                writer.join().unwrap()
+            });
+
+            assert!(most_unfreed <= BUDGET_NODES, "{}: {} retired nodes unfreed, the budget holds {}",
+                    label, most_unfreed, BUDGET_NODES);
+            tracker.unregister(reader);
+            drop(tracker);
+            assert_eq!(log.freed_count(), log.next_id.load(Ordering::Relaxed), "{}: nodes leaked", label);
+        }
+    }
+}
+
+// A thread retires closures over the budget while a reader holds them, then goes
+// idle and never scans its list again. Once the reader has left, a thread over the
+// budget must free them itself instead of waiting for that scan forever. Hyaline
+// flushes a batch as soon as the budget is exceeded, so the garbage is gone once
+// the reader and the idle thread have left and the waiter returns right away.
+#[test]
+fn idle_thread_garbage_is_freed_by_the_waiting_thread() {
+    for policy in [BudgetPolicy::Block, BudgetPolicy::SpinHelp] {
+        for tracker_type in TrackerType::enabled() {
+            let label = format!("{:?} with {:?} policy", tracker_type, policy);
+            let log = Arc::new(FreeLog::default());
+            let budget = MemoryBudget::new((BUDGET_NODES * size_of::<Box<dyn FnOnce() + Send>>()) as u64, policy);
+            let config = TrackerConfig { budget: Some(budget), empty_freq: 4 * BUDGET_NODES,
+                                         batch_size: 4 * BUDGET_NODES, ..TrackerConfig::default() };
+            let mut tracker = MemoryTracker::<TestNode>::build(tracker_type, config);
+            let reader = tracker.register();
+            let idle = tracker.register();
+
+            tracker.start_op(&reader);
+            tracker.start_op(&idle);
+            for _ in 0..2 * BUDGET_NODES {
+                let node = log.node();
+                tracker.retire_foreign(Foreign::closure(Box::new(move || drop(node))), &idle);
+            }
+            tracker.end_op(&idle);
+            tracker.end_op(&reader);
+
+            let done = AtomicBool::new(false);
+            let in_time = thread::scope(|scope| {
+                let (tracker, done) = (&tracker, &done);
+                scope.spawn(move || {
+                    let token = tracker.register();
+                    tracker.admit(&token).unwrap();
+                    done.store(true, Ordering::SeqCst);
+                    tracker.unregister(token);
+                });
+                for _ in 0..500 {
+                    if done.load(Ordering::SeqCst) {
+                        return true;
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+                // Let the waiter go, the idle thread frees its own garbage
+                tracker.admit(&idle).unwrap();
+                false
+            });
+            assert!(in_time, "{}: waited on the garbage of an idle thread", label);
+            assert_eq!(log.freed_count(), 2 * BUDGET_NODES, "{}: idle garbage not freed", label);
+            tracker.unregister(idle);
+            tracker.unregister(reader);
+            tracker.drain();
+        }
+    }
+}
+
+// With the Error policy, ending the operation whose retires went over the budget
+// reports it while a reader holds everything, and so does the next admit. Both
+// succeed again once the reader has left.
+#[test]
+fn end_of_operation_reports_an_exceeded_budget() {
+    for tracker_type in TrackerType::enabled() {
+        let log = Arc::new(FreeLog::default());
+        let budget = MemoryBudget::new((BUDGET_NODES * size_of::<Box<dyn FnOnce() + Send>>()) as u64,
+                                       BudgetPolicy::Error);
+        let config = TrackerConfig { budget: Some(budget), ..TrackerConfig::default() };
+        let tracker = MemoryTracker::<TestNode>::new(&format!("{:?}", tracker_type), config);
+        let reader = tracker.register();
+        let writer = tracker.register();
+
+        let pinned = tracker.pin(&reader);
+        let writing = tracker.pin(&writer);
+        for _ in 0..2 * BUDGET_NODES {
+            let node = log.node();
+            writing.defer(move || drop(node));
+        }
+        assert!(writing.end().is_err(), "{:?}: end of the operation over budget succeeded", tracker_type);
+        assert!(tracker.admit(&writer).is_err(), "{:?}: admit over budget succeeded", tracker_type);
+        drop(pinned);
+        assert!(tracker.admit(&writer).is_ok(), "{:?}: still over budget after the reader left", tracker_type);
+        assert_eq!(log.freed_count(), 2 * BUDGET_NODES, "{:?}: deferred closures not run", tracker_type);
+        tracker.unregister(writer);
+        tracker.unregister(reader);
+    }
+}
+
+// Adaptive Interval mode while a reader stalls on a node: the frequencies move
+// away from the configured ones, and the writer's retired list stays bounded
+// because the epoch keeps advancing past the reader's reservation
+#[cfg(feature = "interval")]
+#[test]
+fn adaptive_interval_stays_bounded_under_a_stalled_reader() {
+    let log = Arc::new(FreeLog::default());
+    let config = TrackerConfig { adaptive: true, ..TrackerConfig::default() };
+    let configured = format!("epoch_freq {}, empty_freq {}", config.epoch_freq, config.empty_freq);
+    // What an epoch of both threads' allocations and one scan period leave behind, twice
+    let bound = 2 * (config.epoch_freq * 2 + config.empty_freq) as u64;
+    let mut tracker = MemoryTracker::<TestNode>::build(TrackerType::Interval, config);
+    let reader = tracker.register();
+    let writer = tracker.register();
+    let shared = AtomicPtr::new(tracker.alloc(log.node(), &writer));
+
+    tracker.start_op(&reader);
+    let id = unsafe { (*tracker.read(&shared, 0, &reader)).id };
+    let mut most_retired = 0;
+    for _ in 0..8 * CHURN {
+        unlink_and_churn(tracker.as_ref(), &log, &shared, &writer);
+        most_retired = most_retired.max(tracker.get_retired_cnt(&writer));
+    }
+    let described = tracker.describe();
+    assert!(!log.is_freed(id), "node freed while the reader holds it");
+    assert!(!described.starts_with(&configured) && !described.contains("after 0 adjustments"),
+            "frequencies did not adapt: {}", described);
+    assert!(most_retired <= bound, "{} retired nodes unfreed under a stalled reader, expected at most {}",
+            most_retired, bound);
+    tracker.end_op(&reader);
+
+    tracker.unregister(reader);
+    tracker.unregister(writer);
+    tracker.drain();
+    assert!(log.is_freed(id), "retired node not freed after the reader left");
+    tracker.reclaim(shared.load(Ordering::SeqCst));
+}
+
+// A name is only accepted when its scheme is compiled in, a known scheme whose
+// feature is off says which feature to enable
+#[test]
+fn lookup_accepts_only_enabled_schemes() {
+    for tracker_type in TrackerType::ALL {
+        match TrackerType::lookup(&format!("{:?}", tracker_type)) {
+            Ok(found) => assert!(found == tracker_type && tracker_type.is_enabled()),
+            Err(TrackerTypeError::Disabled(found)) => assert!(found == tracker_type && !tracker_type.is_enabled()),
+            Err(err) => panic!("{:?}: {}", tracker_type, err),
+        }
+    }
+    assert!(matches!(TrackerType::lookup("Epoch"), Err(TrackerTypeError::Unknown(_))));
+}
+
+// Fails to compile when a TrackerType is added without extending ALL
+#[test]
+fn every_tracker_type_is_covered() {
+    for tracker_type in TrackerType::ALL {
+        let position = match tracker_type {
+            TrackerType::Hyaline => 0,
+            TrackerType::Hyaline1 => 1,
+            TrackerType::Interval => 2,
+            TrackerType::Hazard => 3,
+        };
+        assert_eq!(TrackerType::ALL[position], tracker_type);
+    }
+}
@@ -0,0 +1,352 @@
+use std::mem::{align_of, size_of};
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use crate::trackers::BaseTracker::{BaseTracker, Foreign, Retiree};
+use crate::trackers::OrphanList::OrphanList;
+use crate::util::MemoryBudget::{BudgetExceeded, MemoryBudget};
+use crate::util::Quarantine::SystemRelease;
+use crate::util::RAllocator::RAllocator;
+use crate::util::RetiredMonitorable::{RetiredCounters, RetiredMonitorable, RetiredStats};
+use crate::util::ThreadRegistry::{ThreadRegistry, ThreadToken};
+
+// Hazard pointers a thread can hold at once
+pub(crate) const MAX_SLOTS: usize = 8;
+
+// A link may carry flags in the bits an aligned T leaves free, e.g. the deletion
+// mark of a list. The slot protects the object, so it holds the bare address.
+fn untagged<T>(ptr: *mut T) -> *mut T {
+    ptr.map_addr(|addr| addr & !(align_of::<T>() - 1))
+}
+
+struct HazardInfo<T> {
+    item: Retiree<T>,
+    owner: *const RetiredCounters, // Counters of the retiring thread, its slot outlives it
+    // Foreign entries: the threads that were inside an operation at the retire,
+    // with the number of their operation. No hazard can protect such an entry, it
+    // waits until each of them has left that operation.
+    waits_for: Vec<(usize, u64)>,
+}
+
+// Per-thread state. The hazard slots are read by every scanning thread, the
+// retired list is taken over by threads that help a tracker over its budget.
+#[repr(align(128))]
+struct HazardLocal<T> {
+    slots: [AtomicPtr<T>; MAX_SLOTS],
+    active: AtomicBool, // Between start_op and end_op
+    ops: AtomicU64,     // Operations started
+    since_empty: AtomicUsize, // Retires since the last scan
+    retired: Mutex<Vec<HazardInfo<T>>>,
+    stats: RetiredCounters,
+}
+
+unsafe impl<T: Send> Send for HazardLocal<T> {}
+unsafe impl<T: Send> Sync for HazardLocal<T> {}
+
+impl<T> Default for HazardLocal<T> {
+    fn default() -> Self {
+        HazardLocal {
+            slots: std::array::from_fn(|_| AtomicPtr::new(null_mut())),
+            active: AtomicBool::new(false),
+            ops: AtomicU64::new(0),
+            since_empty: AtomicUsize::new(0),
+            retired: Mutex::new(Vec::new()),
+            stats: RetiredCounters::default(),
+        }
+    }
+}
+
+// Hazard pointers. A thread publishes every pointer it is about to dereference in
+// one of its `slot_num` slots, and a retired object is freed once no slot of any
+// thread holds it. Each thread scans its retired list every `empty_freq` retires.
+pub(crate) struct HazardTracker<T> {
+    slot_num: usize,
+    empty_freq: usize,
+    threads: ThreadRegistry<HazardLocal<T>>,
+    orphans: OrphanList<HazardInfo<T>>, // Left behind by threads that unregistered
+    budget: Option<MemoryBudget>, // Cap on retired but not yet reclaimed bytes
+    allocator: Box<dyn RAllocator>, // Blocks of `Layout::new::<T>()`
+    foreign: SystemRelease, // Memory of retired objects the tracker did not allocate
+}
+
+impl<T> HazardTracker<T> {
+    pub(crate) fn new(slot_num: usize, empty_freq: usize, budget: Option<MemoryBudget>, allocator: Box<dyn RAllocator>,
+                      quarantine: usize) -> Self {
+        assert!(slot_num <= MAX_SLOTS, "Hazard pointers support at most {} slots", MAX_SLOTS);
+        HazardTracker {
+            slot_num,
+            empty_freq: empty_freq.max(1),
+            threads: ThreadRegistry::new(),
+            orphans: OrphanList::new(),
+            budget,
+            allocator,
+            foreign: SystemRelease::new(quarantine),
+        }
+    }
+
+    // Forced reclamation over the budget: an idle thread may never scan its list
+    // again, so take the lists of the other threads and scan them with our own.
+    // A list its owner is working on right now is left to the owner.
+    fn help(&self, token: &ThreadToken) {
+        let local = self.threads.get(token);
+        let mut retired = local.retired.lock().unwrap();
+        self.threads.for_each(|_, other| {
+            if !std::ptr::eq(other, local) {
+                if let Ok(mut theirs) = other.retired.try_lock() {
+                    retired.append(&mut theirs);
+                }
+            }
+        });
+        drop(retired);
+        self.empty(token);
+    }
+
+    // Adopt the orphaned entries, then free every entry of the thread's retired
+    // list that no slot protects
+    fn empty(&self, token: &ThreadToken) {
+        let local = self.threads.get(token);
+        // Orphans are adopted before the hazards are read: an orphan retired after
+        // the read may be protected by a hazard published in between
+        let mut retired = local.retired.lock().unwrap();
+        self.orphans.adopt(&mut retired);
+        let mut hazards = Vec::new();
+        self.threads.for_each(|_, other| {
+            for slot in &other.slots[..self.slot_num] {
+                let ptr = slot.load(Ordering::SeqCst);
+                if !ptr.is_null() {
+                    hazards.push(ptr);
+                }
+            }
+        });
+        hazards.sort_unstable();
+        let ops = self.operations();
+
+        let own: *const RetiredCounters = &local.stats;
+        let (mut own_freed, mut own_bytes, mut bytes) = (0, 0, 0);
+        retired.retain(|info| {
+            let protected = match info.item {
+                Retiree::Object(obj) => hazards.binary_search(&obj).is_ok(),
+                Retiree::Foreign(_) => info.waits_for.iter().any(|&(tid, op)| ops.get(tid) == Some(&Some(op))),
+            };
+            if protected {
+                return true;
+            }
+            let size = self.free_entry(&info.item, Some(token));
+            bytes += size;
+            // Adopted entries are credited to the thread that retired them
+            if info.owner == own {
+                own_freed += 1;
+                own_bytes += size;
+            } else {
+                unsafe { (*info.owner).collect_reclaimed_size(1, size) };
+            }
+            false
+        });
+        local.stats.collect_reclaimed_size(own_freed, own_bytes);
+        if let Some(budget) = &self.budget {
+            budget.credit(bytes);
+        }
+    }
+
+    // The operation every thread is in, by slot, None for the idle ones
+    fn operations(&self) -> Vec<Option<u64>> {
+        let mut ops = Vec::new();
+        self.threads.for_each(|_, local| {
+            let active = local.active.load(Ordering::SeqCst);
+            ops.push(active.then(|| local.ops.load(Ordering::SeqCst)));
+        });
+        ops
+    }
+
+    // Destroy a retired entry, returns the bytes released
+    fn free_entry(&self, item: &Retiree<T>, token: Option<&ThreadToken>) -> u64 {
+        match item {
+            Retiree::Object(obj) => {
+                self.free_block(*obj, token);
+                size_of::<T>() as u64
+            }
+            Retiree::Foreign(foreign) => unsafe { foreign.free(&self.foreign) },
+        }
+    }
+
+    // Add an entry to the thread's retired list and scan the list when it is due
+    fn push_retired(&self, info: HazardInfo<T>, bytes: u64, token: &ThreadToken) {
+        let local = self.threads.get(token);
+        local.stats.collect_retired_size(bytes);
+        if let Some(budget) = &self.budget {
+            budget.charge(bytes);
+        }
+        local.retired.lock().unwrap().push(info);
+        // Forced reclamation: over budget, scan right away
+        if local.since_empty.fetch_add(1, Ordering::Relaxed) + 1 >= self.empty_freq
+            || self.budget.as_ref().is_some_and(|budget| budget.exceeded()) {
+            local.since_empty.store(0, Ordering::Relaxed);
+            self.empty(token);
+        }
+    }
+
+    fn free_block(&self, obj: *mut T, token: Option<&ThreadToken>) {
+        unsafe { std::ptr::drop_in_place(obj) };
+        self.allocator.free_block(obj as *mut u8, token);
+    }
+
+    pub(crate) fn active_threads(&self) -> i64 {
+        let mut active = 0;
+        self.threads.for_each(|_, local| {
+            if local.active.load(Ordering::Acquire) {
+                active += 1;
+            }
+        });
+        active
+    }
+
+    // Free every retired entry, orphans included, once no thread is active
+    pub(crate) fn drain(&mut self) {
+        if self.active_threads() != 0 {
+            return;
+        }
+        let mut orphans = Vec::new();
+        self.orphans.adopt(&mut orphans);
+        self.threads.for_each(|_, local| orphans.append(&mut local.retired.lock().unwrap()));
+        for info in orphans {
+            let bytes = self.free_entry(&info.item, None);
+            unsafe { (*info.owner).collect_reclaimed_size(1, bytes) };
+            if let Some(budget) = &self.budget {
+                budget.credit(bytes);
+            }
+        }
+    }
+}
+
+impl<T: Send> BaseTracker<T> for HazardTracker<T> {
+    fn register(&self) -> ThreadToken {
+        self.threads.register()
+    }
+
+    fn check_token(&self, token: &ThreadToken) {
+        self.threads.check(token);
+    }
+
+    // Entries that are still protected are handed off to the threads that keep running
+    fn unregister(&self, token: ThreadToken) {
+        self.empty(&token);
+        let local = self.threads.get(&token);
+        self.orphans.push(std::mem::take(&mut *local.retired.lock().unwrap()));
+        self.threads.unregister(token);
+    }
+
+    fn alloc(&self, obj: T, token: &ThreadToken) -> *mut T {
+        self.threads.get(token).stats.collect_alloc(size_of::<T>() as u64);
+        let block = self.allocator.alloc_block(token) as *mut T;
+        unsafe { block.write(obj) };
+        block
+    }
+
+    fn reclaim(&self, obj: *mut T) {
+        if !obj.is_null() {
+            self.free_block(obj, None);
+        }
+    }
+
+    // The operation is numbered before it becomes visible, see `operations`
+    fn start_op(&self, token: &ThreadToken) {
+        let local = self.threads.get(token);
+        local.ops.fetch_add(1, Ordering::SeqCst);
+        local.active.store(true, Ordering::SeqCst);
+    }
+
+    fn end_op(&self, token: &ThreadToken) {
+        let local = self.threads.get(token);
+        self.clear_all(token);
+        local.active.store(false, Ordering::SeqCst);
+    }
+
+    // Publish the pointer, then check it is still the one stored in `obj`. If it
+    // is, it was reachable after the hazard became visible and cannot be freed.
+    fn read(&self, obj: &AtomicPtr<T>, idx: usize, token: &ThreadToken) -> *mut T {
+        let slot = &self.threads.get(token).slots[idx];
+        let mut ptr = obj.load(Ordering::Acquire);
+        loop {
+            slot.store(untagged(ptr), Ordering::SeqCst);
+            let current = obj.load(Ordering::SeqCst);
+            if current == ptr {
+                return ptr;
+            }
+            ptr = current;
+        }
+    }
+
+    fn reserve(&self, obj: *mut T, idx: usize, token: &ThreadToken) {
+        self.threads.get(token).slots[idx].store(untagged(obj), Ordering::SeqCst);
+    }
+
+    fn release(&self, idx: usize, token: &ThreadToken) {
+        self.threads.get(token).slots[idx].store(null_mut(), Ordering::Release);
+    }
+
+    fn clear_all(&self, token: &ThreadToken) {
+        for slot in &self.threads.get(token).slots[..self.slot_num] {
+            slot.store(null_mut(), Ordering::Release);
+        }
+    }
+
+    fn retire(&self, obj: *mut T, token: &ThreadToken) {
+        if obj.is_null() {
+            return;
+        }
+        let owner = &self.threads.get(token).stats;
+        let info = HazardInfo { item: Retiree::Object(obj), owner, waits_for: Vec::new() };
+        self.push_retired(info, size_of::<T>() as u64, token);
+    }
+
+    fn retire_foreign(&self, foreign: Foreign, token: &ThreadToken) {
+        assert!(self.threads.get(token).active.load(Ordering::Relaxed), "retire_foreign outside of start_op/end_op");
+        let waits_for = self.operations().into_iter().enumerate()
+            .filter_map(|(tid, op)| op.map(|op| (tid, op)))
+            .collect();
+        let owner = &self.threads.get(token).stats;
+        let bytes = foreign.size();
+        self.push_retired(HazardInfo { item: Retiree::Foreign(foreign), owner, waits_for }, bytes, token);
+    }
+
+    fn get_retired_cnt(&self, token: &ThreadToken) -> u64 {
+        self.threads.get(token).retired.lock().unwrap().len() as u64
+    }
+
+    fn admit(&self, token: &ThreadToken) -> Result<(), BudgetExceeded> {
+        match &self.budget {
+            Some(budget) => budget.wait(|| self.help(token)),
+            None => Ok(()),
+        }
+    }
+
+    fn over_budget(&self) -> bool {
+        self.budget.as_ref().is_some_and(|budget| budget.exceeded())
+    }
+
+    fn active_threads(&self) -> i64 {
+        HazardTracker::active_threads(self)
+    }
+
+    fn drain(&mut self) {
+        HazardTracker::drain(self)
+    }
+
+    fn describe(&self) -> String {
+        format!("slots {}, empty_freq {}, allocator {}", self.slot_num, self.empty_freq, self.allocator.describe())
+    }
+}
+
+impl<T> RetiredMonitorable for HazardTracker<T> {
+    fn collect_retired_stats(&self) -> Vec<(usize, RetiredStats)> {
+        let mut result = Vec::new();
+        self.threads.for_each(|tid, local| result.push((tid, local.stats.report_retired())));
+        result
+    }
+}
+
+impl<T> Drop for HazardTracker<T> {
+    fn drop(&mut self) {
+        self.drain();
+    }
+}
@@ -1,179 +1,443 @@
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::collections::VecDeque;
-use std::ptr;
-mod util;
-mod trackers;
-
-
-struct IntervalTracker<T> {
-    task_num: usize,
-    freq: usize,
-    epoch_freq: usize,
-    collect: bool,
-    retired: Vec<Padded<AtomicU64>>, // Replace list with atomic retired counters
-    reservations: Vec<PaddedAtomic>,
-    retire_counters: Vec<Padded<AtomicU64>>,
-    alloc_counters: Vec<Padded<AtomicU64>>,
-    epoch: AtomicU64,
-}
-
-impl<T> IntervalTracker<T> {
-    fn new(task_num: usize, epoch_freq: usize, empty_freq: usize, collect: bool) -> Self {
-        let retired = (0..task_num).map(|_| Padded { ui: AtomicU64::new(0) }).collect::<Vec<_>>();
-        let reservations = (0..task_num).map(|_| PaddedAtomic { ui: AtomicU64::new(u64::MAX) }).collect::<Vec<_>>();
-        let retire_counters = (0..task_num).map(|_| Padded { ui: AtomicU64::new(0) }).collect::<Vec<_>>();
-        let alloc_counters = (0..task_num).map(|_| Padded { ui: AtomicU64::new(0) }).collect::<Vec<_>>();
-
-        IntervalTracker {
-            task_num,
-            freq: empty_freq,
-            epoch_freq,
-            collect,
-            retired,
-            reservations,
-            retire_counters,
-            alloc_counters,
-            epoch: AtomicU64::new(0),
-        }
-    }
-
-    fn get_epoch(&self) -> u64 {
-        self.epoch.load(Ordering::Acquire)
-    }
-
-    fn alloc(&self, tid: usize) -> *mut T {
-        self.alloc_counters[tid].ui.fetch_add(1, Ordering::SeqCst);
-        if self.alloc_counters[tid].ui.load(Ordering::Acquire) % (self.epoch_freq as u64 * self.task_num as u64) == 0 {
-            self.epoch.fetch_add(1, Ordering::AcqRel);
-        }
-        
-        // Allocate memory for T and its associated birth epoch
-        let block = Box::into_raw(Box::new(T::default()));
-        let birth_epoch = unsafe { (block as *mut u8).offset(std::mem::size_of::<T>() as isize) as *mut u64 };
-        unsafe { *birth_epoch = self.get_epoch() };
-
-        block
-    }
-
-    fn read_birth(&self, obj: *mut T) -> u64 {
-        unsafe {
-            let birth_epoch = (obj as *mut u8).offset(std::mem::size_of::<T>() as isize) as *mut u64;
-            *birth_epoch
-        }
-    }
-
-    fn reclaim(&self, obj: *mut T) {
-        if !obj.is_null() {
-            unsafe { Box::from_raw(obj) }; // Automatically drops the object, reclaiming memory
-        }
-    }
-
-    fn start_op(&self, tid: usize) {
-        let e = self.get_epoch();
-        self.reservations[tid].ui.store(e, Ordering::SeqCst);
-    }
-
-    fn end_op(&self, tid: usize) {
-        self.reservations[tid].ui.store(u64::MAX, Ordering::SeqCst);
-    }
-
-    fn reserve(&self, tid: usize) {
-        self.start_op(tid);
-    }
-
-    fn clear(&self, tid: usize) {
-        self.end_op(tid);
-    }
-
-    fn validate(&self, tid: usize) -> bool {
-        self.reservations[tid].ui.load(Ordering::Acquire) == self.get_epoch()
-    }
-
-    fn increment_epoch(&self) {
-        self.epoch.fetch_add(1, Ordering::AcqRel);
-    }
-
-    fn retire(&self, obj: *mut T, birth_epoch: u64, tid: usize) {
-        if obj.is_null() { return; }
-
-        let retire_epoch = self.get_epoch();
-        let info = IntervalInfo {
-            obj,
-            birth_epoch,
-            retire_epoch,
-        };
-
-        let my_trash = &mut self.retired[tid].ui;
-        my_trash.push_back(info);
-
-        self.retire_counters[tid].ui.fetch_add(1, Ordering::SeqCst);
-
-        if self.collect && self.retire_counters[tid].ui.load(Ordering::Acquire) % self.freq as u64 == 0 {
-            self.empty(tid);
-        }
-    }
-
-    fn retire_with_birth(&self, obj: *mut T, tid: usize) {
-        self.retire(obj, self.read_birth(obj), tid);
-    }
-
-    fn conflict(&self, reserv_epoch: &[u64], birth_epoch: u64, retire_epoch: u64) -> bool {
-        for &r_epoch in reserv_epoch {
-            if r_epoch >= birth_epoch && r_epoch <= retire_epoch {
-                return true;
-            }
-        }
-        false
-    }
-
-    fn empty(&self, tid: usize) {
-        let mut reserv_epoch = Vec::with_capacity(self.task_num);
-        for i in 0..self.task_num {
-            reserv_epoch.push(self.reservations[i].ui.load(Ordering::Acquire));
-        }
-
-        let my_trash = &mut self.retired[tid].ui;
-        let mut i = 0;
-        while i < my_trash.len() {
-            let res = &my_trash[i];
-            if !self.conflict(&reserv_epoch, res.birth_epoch, res.retire_epoch) {
-                my_trash.remove(i);
-                self.reclaim(res.obj);
-                self.dec_retired(tid);
-            } else {
-                i += 1;
-            }
-        }
-    }
-
-    fn collecting(&self) -> bool {
-        self.collect
-    }
-}
-
-struct IntervalInfo<T> {
-    obj: *mut T,
-    birth_epoch: u64,
-    retire_epoch: u64,
-}
-
-impl<T> IntervalInfo<T> {
-    fn new(obj: *mut T, birth_epoch: u64, retire_epoch: u64) -> Self {
-        IntervalInfo {
-            obj,
-            birth_epoch,
-            retire_epoch,
-        }
-    }
-}
-
-impl Default for IntervalInfo<i32> {
-    fn default() -> Self {
-        IntervalInfo {
-            obj: std::ptr::null_mut(),
-            birth_epoch: 0,
-            retire_epoch: 0,
-        }
-    }
-}
\ No newline at end of file
+use std::alloc::Layout;
+use std::cell::UnsafeCell;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use crate::trackers::BaseTracker::{BaseTracker, Foreign, Retiree};
+use crate::trackers::OrphanList::OrphanList;
+use crate::util::MemoryBudget::{BudgetExceeded, MemoryBudget};
+use crate::util::Quarantine::SystemRelease;
+use crate::util::RAllocator::RAllocator;
+use crate::util::RetiredMonitorable::{RetiredCounters, RetiredMonitorable, RetiredStats};
+use crate::util::ThreadRegistry::{ThreadRegistry, ThreadToken};
+
+// Bounds of the frequencies in adaptive mode
+const MAX_EPOCH_FREQ: usize = 1 << 16;
+const MAX_EMPTY_FREQ: usize = 1 << 12;
+// Scans a thread runs between two adjustments of the frequencies
+const TUNE_SCANS: u64 = 8;
+// Share of the scanned entries that a scan frees. Below LOW_SUCCESS most scans
+// are wasted and are spaced out, above HIGH_SUCCESS they are brought closer.
+const LOW_SUCCESS: f64 = 0.25;
+const HIGH_SUCCESS: f64 = 0.75;
+
+// An object together with the epoch it was allocated in
+#[repr(C)]
+struct Block<T> {
+    obj: T, // Must stay the first field, the data structure only sees the object
+    birth_epoch: u64,
+}
+
+// Layout of the blocks the tracker allocates for objects of type T
+pub(crate) fn block_layout<T>() -> Layout {
+    Layout::new::<Block<T>>()
+}
+
+struct IntervalInfo<T> {
+    item: Retiree<T>,
+    birth_epoch: u64,
+    retire_epoch: u64,
+    owner: *const RetiredCounters, // Counters of the retiring thread, its slot outlives it
+}
+
+// What a thread measured since the frequencies were last adjusted
+#[derive(Default)]
+struct TuneWindow {
+    allocs: u64,
+    retires: u64,
+    scans: u64,
+    scanned: u64, // Entries looked at by the scans
+    freed: u64,   // Entries the scans could free
+}
+
+// Per-thread state. The reservation is read by every scanning thread, the
+// retired list is taken over by threads that help a tracker over its budget,
+// the tuning window is touched only by the owner (and by `drain`).
+#[repr(align(128))]
+struct IntervalLocal<T> {
+    lower: AtomicU64, // Reserved interval of the current operation, u64::MAX when idle
+    upper: AtomicU64,
+    alloc_count: AtomicU64,
+    since_empty: AtomicUsize, // Retires since the last scan
+    retired: Mutex<Vec<IntervalInfo<T>>>,
+    window: UnsafeCell<TuneWindow>,
+    stats: RetiredCounters,
+}
+
+unsafe impl<T: Send> Send for IntervalLocal<T> {}
+unsafe impl<T: Send> Sync for IntervalLocal<T> {}
+
+impl<T> Default for IntervalLocal<T> {
+    fn default() -> Self {
+        IntervalLocal {
+            lower: AtomicU64::new(u64::MAX),
+            upper: AtomicU64::new(u64::MAX),
+            alloc_count: AtomicU64::new(0),
+            since_empty: AtomicUsize::new(0),
+            retired: Mutex::new(Vec::new()),
+            window: UnsafeCell::new(TuneWindow::default()),
+            stats: RetiredCounters::default(),
+        }
+    }
+}
+
+// Interval-based reclamation. Every object records the epoch it was born in and
+// the epoch it was retired in, and is freed once no thread reserves an epoch in
+// between. A thread reserves the epochs from the start of its operation up to the
+// epoch of its latest `read` (2GEIBR), so every pointer must be loaded through `read`.
+//
+// The epoch advances every `epoch_freq` allocations per thread and a thread scans
+// its retired list every `empty_freq` retires. In adaptive mode both are starting
+// values that are retuned from the retire rate, the share of entries the scans
+// actually free and the number of threads.
+pub(crate) struct IntervalTracker<T> {
+    epoch_freq: AtomicUsize,
+    empty_freq: AtomicUsize,
+    collect: bool,
+    adaptive: bool,
+    adjustments: AtomicU64, // Times the adaptive mode changed the frequencies
+    epoch: AtomicU64,
+    threads: ThreadRegistry<IntervalLocal<T>>,
+    orphans: OrphanList<IntervalInfo<T>>, // Left behind by threads that unregistered
+    budget: Option<MemoryBudget>, // Cap on retired but not yet reclaimed bytes
+    allocator: Box<dyn RAllocator>, // Blocks of `block_layout`
+    foreign: SystemRelease, // Memory of retired objects the tracker did not allocate
+}
+
+impl<T> IntervalTracker<T> {
+    pub(crate) fn new(epoch_freq: usize, empty_freq: usize, collect: bool, adaptive: bool,
+                      budget: Option<MemoryBudget>, allocator: Box<dyn RAllocator>, quarantine: usize) -> Self {
+        IntervalTracker {
+            epoch_freq: AtomicUsize::new(epoch_freq.max(1)),
+            empty_freq: AtomicUsize::new(empty_freq.max(1)),
+            collect,
+            adaptive,
+            adjustments: AtomicU64::new(0),
+            epoch: AtomicU64::new(0),
+            threads: ThreadRegistry::new(),
+            orphans: OrphanList::new(),
+            budget,
+            allocator,
+            foreign: SystemRelease::new(quarantine),
+        }
+    }
+
+    fn get_epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    fn read_birth(&self, obj: *mut T) -> u64 {
+        unsafe { (*(obj as *mut Block<T>)).birth_epoch }
+    }
+
+    // Release the block of `obj`, returns the bytes released
+    fn free_block(&self, obj: *mut T, token: Option<&ThreadToken>) -> u64 {
+        unsafe { std::ptr::drop_in_place(obj as *mut Block<T>) };
+        self.allocator.free_block(obj as *mut u8, token);
+        size_of::<Block<T>>() as u64
+    }
+
+    // Add an entry to the thread's retired list and scan the list when it is due
+    fn push_retired(&self, item: Retiree<T>, birth_epoch: u64, bytes: u64, token: &ThreadToken) {
+        let local = self.threads.get(token);
+        local.stats.collect_retired_size(bytes);
+        if let Some(budget) = &self.budget {
+            budget.charge(bytes);
+        }
+        let info = IntervalInfo { item, birth_epoch, retire_epoch: self.get_epoch(), owner: &local.stats };
+        local.retired.lock().unwrap().push(info);
+        if self.adaptive {
+            unsafe { (*local.window.get()).retires += 1 };
+        }
+
+        if !self.collect {
+            return;
+        }
+        // Forced reclamation: over budget, scan right away
+        if local.since_empty.fetch_add(1, Ordering::Relaxed) + 1 >= self.empty_freq.load(Ordering::Relaxed)
+            || self.budget.as_ref().is_some_and(|budget| budget.exceeded()) {
+            local.since_empty.store(0, Ordering::Relaxed);
+            self.empty(token);
+        }
+    }
+
+    // Destroy a retired entry, returns the bytes released
+    fn free_entry(&self, item: &Retiree<T>, token: Option<&ThreadToken>) -> u64 {
+        match item {
+            Retiree::Object(obj) => self.free_block(*obj, token),
+            Retiree::Foreign(foreign) => unsafe { foreign.free(&self.foreign) },
+        }
+    }
+
+    fn conflict(reservations: &[(u64, u64)], birth_epoch: u64, retire_epoch: u64) -> bool {
+        reservations.iter().any(|&(lower, upper)| lower <= retire_epoch && upper >= birth_epoch)
+    }
+
+    // Forced reclamation over the budget: an idle thread may never scan its list
+    // again, so take the lists of the other threads and scan them with our own.
+    // A list its owner is working on right now is left to the owner.
+    fn help(&self, token: &ThreadToken) {
+        let local = self.threads.get(token);
+        let mut retired = local.retired.lock().unwrap();
+        self.threads.for_each(|_, other| {
+            if !std::ptr::eq(other, local) {
+                if let Ok(mut theirs) = other.retired.try_lock() {
+                    retired.append(&mut theirs);
+                }
+            }
+        });
+        drop(retired);
+        self.empty(token);
+    }
+
+    // Adopt the orphaned entries, then free every entry of the thread's retired
+    // list that no reservation covers
+    fn empty(&self, token: &ThreadToken) {
+        let local = self.threads.get(token);
+        // Orphans are adopted before the reservations are read: an orphan retired
+        // after the read may be covered by a reservation made in between
+        let mut retired = local.retired.lock().unwrap();
+        self.orphans.adopt(&mut retired);
+        let mut reservations = Vec::new();
+        self.threads.for_each(|_, other| {
+            let lower = other.lower.load(Ordering::SeqCst);
+            if lower != u64::MAX {
+                reservations.push((lower, other.upper.load(Ordering::SeqCst)));
+            }
+        });
+
+        let scanned = retired.len() as u64;
+        let own: *const RetiredCounters = &local.stats;
+        let (mut own_freed, mut own_bytes, mut bytes) = (0, 0, 0);
+        retired.retain(|info| {
+            if IntervalTracker::<T>::conflict(&reservations, info.birth_epoch, info.retire_epoch) {
+                return true;
+            }
+            let size = self.free_entry(&info.item, Some(token));
+            bytes += size;
+            // Adopted entries are credited to the thread that retired them
+            if info.owner == own {
+                own_freed += 1;
+                own_bytes += size;
+            } else {
+                unsafe { (*info.owner).collect_reclaimed_size(1, size) };
+            }
+            false
+        });
+        let freed = scanned - retired.len() as u64;
+        local.stats.collect_reclaimed_size(own_freed, own_bytes);
+        if let Some(budget) = &self.budget {
+            budget.credit(bytes);
+        }
+        if self.adaptive {
+            self.tune(local, scanned, freed);
+        }
+    }
+
+    // Adaptive mode: once a thread has run TUNE_SCANS scans, derive new frequencies
+    // from what it measured. Threads tune independently, the last one to store wins.
+    fn tune(&self, local: &IntervalLocal<T>, scanned: u64, freed: u64) {
+        let window = unsafe { &mut *local.window.get() };
+        window.scans += 1;
+        window.scanned += scanned;
+        window.freed += freed;
+        if window.scans < TUNE_SCANS {
+            return;
+        }
+
+        let threads = self.threads.registered().max(1);
+        let old_empty_freq = self.empty_freq.load(Ordering::Relaxed);
+        let old_epoch_freq = self.epoch_freq.load(Ordering::Relaxed);
+
+        let success = window.freed as f64 / window.scanned.max(1) as f64;
+        let mut empty_freq = old_empty_freq;
+        if success < LOW_SUCCESS {
+            empty_freq *= 2;
+        } else if success > HIGH_SUCCESS {
+            empty_freq /= 2;
+        }
+        // A scan reads the reservation of every thread, amortize it over at least twice as many retires
+        let min_empty_freq = 2 * threads;
+        empty_freq = empty_freq.clamp(min_empty_freq, MAX_EMPTY_FREQ.max(min_empty_freq));
+
+        // Every thread advances the epoch once per epoch_freq * threads of its own
+        // allocations, so the epoch moves about once per epoch_freq allocations of
+        // this thread. Aim for two advances between scans, i.e. over empty_freq retires.
+        let mut epoch_freq = old_epoch_freq;
+        if window.retires != 0 {
+            let retire_rate = window.retires as f64 / window.allocs.max(1) as f64;
+            epoch_freq = ((empty_freq as f64 / (2.0 * retire_rate)) as usize).clamp(1, MAX_EPOCH_FREQ);
+        }
+
+        if empty_freq != old_empty_freq || epoch_freq != old_epoch_freq {
+            self.empty_freq.store(empty_freq, Ordering::Relaxed);
+            self.epoch_freq.store(epoch_freq, Ordering::Relaxed);
+            self.adjustments.fetch_add(1, Ordering::Relaxed);
+        }
+        *window = TuneWindow::default();
+    }
+
+    pub(crate) fn active_threads(&self) -> i64 {
+        let mut active = 0;
+        self.threads.for_each(|_, local| {
+            if local.lower.load(Ordering::Acquire) != u64::MAX {
+                active += 1;
+            }
+        });
+        active
+    }
+
+    // Free every retired entry, orphans included, once no thread is active
+    pub(crate) fn drain(&mut self) {
+        if self.active_threads() != 0 {
+            return;
+        }
+        let mut orphans = Vec::new();
+        self.orphans.adopt(&mut orphans);
+        self.threads.for_each(|_, local| orphans.append(&mut local.retired.lock().unwrap()));
+        for info in orphans {
+            let bytes = self.free_entry(&info.item, None);
+            unsafe { (*info.owner).collect_reclaimed_size(1, bytes) };
+            if let Some(budget) = &self.budget {
+                budget.credit(bytes);
+            }
+        }
+    }
+}
+
+impl<T: Send> BaseTracker<T> for IntervalTracker<T> {
+    fn register(&self) -> ThreadToken {
+        self.threads.register()
+    }
+
+    fn check_token(&self, token: &ThreadToken) {
+        self.threads.check(token);
+    }
+
+    // Entries that are still reserved are handed off to the threads that keep running
+    fn unregister(&self, token: ThreadToken) {
+        self.empty(&token);
+        let local = self.threads.get(&token);
+        self.orphans.push(std::mem::take(&mut *local.retired.lock().unwrap()));
+        self.threads.unregister(token);
+    }
+
+    fn alloc(&self, obj: T, token: &ThreadToken) -> *mut T {
+        let local = self.threads.get(token);
+        let count = local.alloc_count.fetch_add(1, Ordering::Relaxed) + 1;
+        let period = (self.epoch_freq.load(Ordering::Relaxed) * self.threads.registered().max(1)) as u64;
+        if count.is_multiple_of(period) {
+            self.epoch.fetch_add(1, Ordering::AcqRel);
+        }
+        if self.adaptive {
+            unsafe { (*local.window.get()).allocs += 1 };
+        }
+        local.stats.collect_alloc(size_of::<Block<T>>() as u64);
+        let block = self.allocator.alloc_block(token) as *mut Block<T>;
+        unsafe { block.write(Block { obj, birth_epoch: self.get_epoch() }) };
+        block as *mut T
+    }
+
+    fn reclaim(&self, obj: *mut T) {
+        if !obj.is_null() {
+            self.free_block(obj, None);
+        }
+    }
+
+    fn start_op(&self, token: &ThreadToken) {
+        let local = self.threads.get(token);
+        let epoch = self.get_epoch();
+        // Upper first, a scanner that sees the new lower bound also sees a valid upper one
+        local.upper.store(epoch, Ordering::SeqCst);
+        local.lower.store(epoch, Ordering::SeqCst);
+    }
+
+    fn end_op(&self, token: &ThreadToken) {
+        self.threads.get(token).lower.store(u64::MAX, Ordering::SeqCst);
+    }
+
+    // Extend the reservation to the current epoch until the pointer is read in it,
+    // so the object read was born no later than the upper bound. One interval
+    // covers every slot, `idx` does not matter.
+    fn read(&self, obj: &AtomicPtr<T>, _idx: usize, token: &ThreadToken) -> *mut T {
+        let local = self.threads.get(token);
+        loop {
+            let ptr = obj.load(Ordering::Acquire);
+            let epoch = self.get_epoch();
+            if local.upper.load(Ordering::Relaxed) == epoch {
+                return ptr;
+            }
+            local.upper.store(epoch, Ordering::SeqCst);
+        }
+    }
+
+    fn retire(&self, obj: *mut T, token: &ThreadToken) {
+        if obj.is_null() {
+            return;
+        }
+        self.push_retired(Retiree::Object(obj), self.read_birth(obj), size_of::<Block<T>>() as u64, token);
+    }
+
+    // Nothing the thread reads through the tracker can be the foreign entry, so it
+    // is born in epoch 0: every reservation made before its retirement covers it
+    fn retire_foreign(&self, foreign: Foreign, token: &ThreadToken) {
+        assert!(self.threads.get(token).lower.load(Ordering::Relaxed) != u64::MAX,
+                "retire_foreign outside of start_op/end_op");
+        let bytes = foreign.size();
+        self.push_retired(Retiree::Foreign(foreign), 0, bytes, token);
+    }
+
+    fn get_retired_cnt(&self, token: &ThreadToken) -> u64 {
+        self.threads.get(token).retired.lock().unwrap().len() as u64
+    }
+
+    // Unlike Hyaline, a scan can run inside an operation, but only entries
+    // retired before the oldest reservation can be freed
+    fn admit(&self, token: &ThreadToken) -> Result<(), BudgetExceeded> {
+        match &self.budget {
+            Some(budget) => budget.wait(|| self.help(token)),
+            None => Ok(()),
+        }
+    }
+
+    fn over_budget(&self) -> bool {
+        self.budget.as_ref().is_some_and(|budget| budget.exceeded())
+    }
+
+    fn active_threads(&self) -> i64 {
+        IntervalTracker::active_threads(self)
+    }
+
+    fn drain(&mut self) {
+        IntervalTracker::drain(self)
+    }
+
+    fn describe(&self) -> String {
+        let mut result = format!("epoch_freq {}, empty_freq {}",
+                                 self.epoch_freq.load(Ordering::Relaxed),
+                                 self.empty_freq.load(Ordering::Relaxed));
+        if self.adaptive {
+            result.push_str(&format!(", adaptive after {} adjustments", self.adjustments.load(Ordering::Relaxed)));
+        }
+        if !self.collect {
+            result.push_str(", no collection");
+        }
+        result.push_str(&format!(", allocator {}", self.allocator.describe()));
+        result
+    }
+
+}
+
+impl<T> RetiredMonitorable for IntervalTracker<T> {
+    fn collect_retired_stats(&self) -> Vec<(usize, RetiredStats)> {
+        let mut result = Vec::new();
+        self.threads.for_each(|tid, local| result.push((tid, local.stats.report_retired())));
+        result
+    }
+}
+
+impl<T> Drop for IntervalTracker<T> {
+    fn drop(&mut self) {
+        self.drain();
+    }
+}
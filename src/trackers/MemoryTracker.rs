@@ -1,131 +1,447 @@
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::collections::HashMap;
-
-#[derive(Debug)]
-enum TrackerType {
-    NIL,
-    RCPU,
-    Interval,
-    Range,
-    RangeNew,
-    QSBR,
-    Hazard,
-    HazardDynamic,
-    HE,
-}
-
-
-struct MemoryTracker<T> {
-    tracker: Option<Box<dyn BaseTracker<T>>>,
-    tracker_type: TrackerType,
-    slot_renamers: Vec<Vec<usize>>,
-}
-
-impl<T> MemoryTracker<T> {
-    fn new(task_num: usize, slot_num: usize, epoch_freq: usize, empty_freq: usize, collect: bool, tracker_type: String) -> Self {
-        let mut tracker: Option<Box<dyn BaseTracker<T>>> = None;
-        let tracker_type_enum = match tracker_type.as_str() {
-            "NIL" => TrackerType::NIL,
-            "RCU" => TrackerType::RCPU,
-            "Interval" => TrackerType::Interval,
-            "Range_new" => TrackerType::RangeNew,
-            "Hazard" => TrackerType::Hazard,
-            "HE" => TrackerType::HE,
-            "QSBR" => TrackerType::QSBR,
-            _ => panic!("Unknown tracker type"),
-        };
-
-        let slot_renamers = (0..task_num).map(|_| (0..slot_num).collect::<Vec<usize>>()).collect::<Vec<_>>();
-
-        match tracker_type_enum {
-            TrackerType::RCU => {
-                // Instantiate the RCPU Tracker (similar to the C++ code).
-                // tracker = Some(Box::new(RCPUTracker::new()));
-            },
-            TrackerType::Interval => {
-                // Instantiate the Interval Tracker.
-                // tracker = Some(Box::new(IntervalTracker::new()));
-            },
-            TrackerType::NIL => {
-                // Instantiate NIL Tracker.
-                // tracker = Some(Box::new(NilTracker::new()));
-            },
-            _ => {}
-        }
-
-        MemoryTracker {
-            tracker,
-            tracker_type: tracker_type_enum,
-            slot_renamers,
-        }
-    }
-
-    fn alloc(&self) -> *mut T {
-        self.tracker.as_ref().expect("Tracker is not initialized").alloc()
-    }
-
-    fn alloc_tid(&self, tid: usize) -> *mut T {
-        self.tracker.as_ref().expect("Tracker is not initialized").alloc_tid(tid)
-    }
-
-    fn reclaim(&self, obj: *mut T) {
-        if let Some(tracker) = &self.tracker {
-            tracker.reclaim(obj);
-        }
-    }
-
-    fn reclaim_tid(&self, obj: *mut T, tid: usize) {
-        if let Some(tracker) = &self.tracker {
-            tracker.reclaim_tid(obj, tid);
-        }
-    }
-
-    fn start_op(&self, tid: usize) {
-        if let Some(tracker) = &self.tracker {
-            tracker.start_op(tid);
-        }
-    }
-
-    fn end_op(&self, tid: usize) {
-        if let Some(tracker) = &self.tracker {
-            tracker.end_op(tid);
-        }
-    }
-
-    fn read(&self, obj: &std::sync::atomic::Atomic<T>, idx: usize, tid: usize) -> *mut T {
-        self.tracker.as_ref().expect("Tracker is not initialized").read(obj, idx, tid)
-    }
-
-    fn transfer(&self, src_idx: usize, dst_idx: usize, tid: usize) {
-        if let Some(tracker) = &self.tracker {
-            tracker.transfer(src_idx, dst_idx, tid);
-        }
-    }
-
-    fn release(&self, idx: usize, tid: usize) {
-        if let Some(tracker) = &self.tracker {
-            tracker.release(idx, tid);
-        }
-    }
-
-    fn clear_all(&self, tid: usize) {
-        if let Some(tracker) = &self.tracker {
-            tracker.clear_all(tid);
-        }
-    }
-
-    fn retire(&self, obj: *mut T, tid: usize) {
-        if let Some(tracker) = &self.tracker {
-            tracker.retire(obj, tid);
-        }
-    }
-
-    fn get_retired_cnt(&self, tid: usize) -> u64 {
-        if let Some(tracker) = &self.tracker {
-            tracker.get_retired_cnt(tid)
-        } else {
-            0
-        }
-    }
-}
+#[cfg(any(feature = "hyaline", feature = "interval", feature = "hazard"))]
+use std::alloc::Layout;
+use std::cell::{Cell, UnsafeCell};
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::sync::atomic::AtomicPtr;
+use crate::trackers::BaseTracker::{BaseTracker, Foreign};
+#[cfg(feature = "hazard")]
+use crate::trackers::HazardTracker::HazardTracker;
+#[cfg(feature = "interval")]
+use crate::trackers::IntervalTracker::{block_layout as interval_block_layout, IntervalTracker};
+use crate::trackers::RetireChecker::RetireChecker;
+#[cfg(feature = "hyaline")]
+use crate::trackers::hyaline_alg::{self, HeadLayout, MemoryTracker as HyalineTracker};
+use crate::util::Backoff::BackoffStrategy;
+use crate::util::MemoryBudget::{BudgetExceeded, MemoryBudget};
+#[cfg(any(feature = "hyaline", feature = "interval", feature = "hazard"))]
+use crate::util::Quarantine::QuarantineAllocator;
+use crate::util::RAllocator::AllocatorType;
+use crate::util::RetiredMonitorable::{RetiredMonitorable, RetiredStats};
+#[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+use crate::util::StateExport::RetirementState;
+use crate::util::ThreadRegistry::{ThreadRegistry, ThreadToken};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackerType {
+    Hyaline,
+    Hyaline1, // Hyaline with a single-width head per thread
+    Interval,
+    Hazard,
+}
+
+impl TrackerType {
+    // Every scheme the crate knows, whether or not its feature is enabled
+    pub const ALL: [TrackerType; 4] =
+        [TrackerType::Hyaline, TrackerType::Hyaline1, TrackerType::Interval, TrackerType::Hazard];
+
+    // Any known scheme, see `lookup` for one that can be built
+    pub fn from_name(name: &str) -> Option<TrackerType> {
+        match name {
+            "Hyaline" => Some(TrackerType::Hyaline),
+            "Hyaline1" => Some(TrackerType::Hyaline1),
+            "Interval" => Some(TrackerType::Interval),
+            "Hazard" => Some(TrackerType::Hazard),
+            _ => None,
+        }
+    }
+
+    // Cargo feature that compiles the scheme in
+    pub fn feature(self) -> &'static str {
+        match self {
+            TrackerType::Hyaline | TrackerType::Hyaline1 => "hyaline",
+            TrackerType::Interval => "interval",
+            TrackerType::Hazard => "hazard",
+        }
+    }
+
+    pub fn is_enabled(self) -> bool {
+        match self {
+            TrackerType::Hyaline | TrackerType::Hyaline1 => cfg!(feature = "hyaline"),
+            TrackerType::Interval => cfg!(feature = "interval"),
+            TrackerType::Hazard => cfg!(feature = "hazard"),
+        }
+    }
+
+    // The schemes the dispatcher can build, the conformance tests run against each
+    pub fn enabled() -> impl Iterator<Item = TrackerType> {
+        TrackerType::ALL.into_iter().filter(|tracker_type| tracker_type.is_enabled())
+    }
+
+    // The scheme called `name`, if it is compiled in
+    pub fn lookup(name: &str) -> Result<TrackerType, TrackerTypeError> {
+        match TrackerType::from_name(name) {
+            Some(tracker_type) if tracker_type.is_enabled() => Ok(tracker_type),
+            Some(tracker_type) => Err(TrackerTypeError::Disabled(tracker_type)),
+            None => Err(TrackerTypeError::Unknown(name.to_string())),
+        }
+    }
+}
+
+// Why a tracker name cannot be built
+#[derive(Debug)]
+pub enum TrackerTypeError {
+    Unknown(String),
+    Disabled(TrackerType), // Known, but its feature is off in this build
+}
+
+impl fmt::Display for TrackerTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let enabled: Vec<TrackerType> = TrackerType::enabled().collect();
+        match self {
+            TrackerTypeError::Unknown(name) => write!(f, "unknown tracker type {}, expected one of {:?}", name, enabled),
+            TrackerTypeError::Disabled(tracker_type) =>
+                write!(f, "tracker type {:?} is not compiled in, enable the \"{}\" feature (available: {:?})",
+                       tracker_type, tracker_type.feature(), enabled),
+        }
+    }
+}
+
+// Parameters of a tracker. Each scheme only looks at the ones it understands.
+pub struct TrackerConfig {
+    pub slot_num: usize,     // Reservation slots per thread
+    pub epoch_freq: usize,   // Interval: allocations per thread between epoch advances
+    pub empty_freq: usize,   // Interval, Hazard: retires between scans of the retired list
+    pub adaptive: bool,      // Interval: retune epoch_freq and empty_freq at runtime
+    pub collect: bool,       // Interval: free retired objects at all
+    pub batch_size: usize,   // Hyaline: retires collected before a batch is inserted
+    pub budget: Option<MemoryBudget>,
+    pub allocator: AllocatorType, // Where the blocks holding the objects come from
+    pub backoff: BackoffStrategy, // Wait between retries of a failed CAS, in Hyaline and the map
+    pub quarantine: usize,   // Use-after-free mode: freed blocks held poisoned, 0 for off
+    pub check_retire: bool,  // Catch double retires and, when single-threaded, retires of linked nodes
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        TrackerConfig {
+            slot_num: 3,
+            epoch_freq: 150,
+            empty_freq: 30,
+            adaptive: false,
+            collect: true,
+            batch_size: 1,
+            budget: None,
+            allocator: AllocatorType::System,
+            backoff: BackoffStrategy::None,
+            quarantine: 0,
+            check_retire: false,
+        }
+    }
+}
+
+// What the dispatcher keeps per thread, only touched by the owning thread
+#[derive(Default)]
+struct LocalState {
+    slots: UnsafeCell<Vec<usize>>, // Logical reservation slot to physical slot of the tracker
+    overdrawn: Cell<bool>, // A retire of the current operation left the tracker over its budget
+}
+
+unsafe impl Sync for LocalState {}
+
+impl LocalState {
+    // The state of a slot is left as its previous owner shuffled it
+    fn reset(&self, slot_num: usize) {
+        let slots = unsafe { &mut *self.slots.get() };
+        slots.clear();
+        slots.extend(0..slot_num);
+        self.overdrawn.set(false);
+    }
+
+    fn physical(&self, idx: usize) -> usize {
+        unsafe { (&*self.slots.get())[idx] }
+    }
+
+    fn swap(&self, src_idx: usize, dst_idx: usize) {
+        unsafe { (&mut *self.slots.get()).swap(src_idx, dst_idx) };
+    }
+}
+
+// Picks the reclamation scheme by name and forwards every call to it. Slot
+// indices passed in are logical, `locals` maps them to physical slots of the
+// tracker so that `transfer` never has to copy a reservation.
+pub struct MemoryTracker<T> {
+    tracker: Box<dyn BaseTracker<T>>,
+    tracker_type: TrackerType,
+    slot_num: usize,
+    locals: ThreadRegistry<LocalState>, // Indexed by the tracker's tokens
+    checker: Option<RetireChecker>, // Checking mode, see RetireChecker
+}
+
+impl<T: Send + 'static> MemoryTracker<T> {
+    pub fn new(tracker_type: &str, config: TrackerConfig) -> Self {
+        let tracker_type = TrackerType::lookup(tracker_type).unwrap_or_else(|err| panic!("{}", err));
+        let slot_num = config.slot_num;
+        let checker = config.check_retire.then(RetireChecker::new);
+        let tracker = MemoryTracker::build(tracker_type, config);
+        MemoryTracker { tracker, tracker_type, slot_num, locals: ThreadRegistry::new(), checker }
+    }
+
+    // The scheme itself, without the slot renaming of the dispatcher
+    #[cfg_attr(not(any(feature = "hyaline", feature = "interval", feature = "hazard")), allow(unused_variables))]
+    pub(crate) fn build(tracker_type: TrackerType, config: TrackerConfig) -> Box<dyn BaseTracker<T>> {
+        // Each scheme wraps the object in a block of its own layout
+        #[cfg(any(feature = "hyaline", feature = "interval", feature = "hazard"))]
+        let allocator = |layout: Layout| {
+            QuarantineAllocator::wrap(config.allocator.build(layout), layout, config.quarantine)
+        };
+        match tracker_type {
+            #[cfg(feature = "hyaline")]
+            TrackerType::Hyaline | TrackerType::Hyaline1 => {
+                let layout = if tracker_type == TrackerType::Hyaline { HeadLayout::Wide } else { HeadLayout::PerThread };
+                Box::new(HyalineTracker::new(config.batch_size, config.budget, allocator(hyaline_alg::block_layout::<T>()),
+                                             config.quarantine, layout, config.backoff))
+            }
+            #[cfg(feature = "interval")]
+            TrackerType::Interval => Box::new(IntervalTracker::new(
+                config.epoch_freq, config.empty_freq, config.collect, config.adaptive, config.budget,
+                allocator(interval_block_layout::<T>()), config.quarantine)),
+            #[cfg(feature = "hazard")]
+            TrackerType::Hazard => Box::new(HazardTracker::new(
+                config.slot_num, config.empty_freq, config.budget, allocator(Layout::new::<T>()), config.quarantine)),
+            #[allow(unreachable_patterns)]
+            disabled => panic!("{}", TrackerTypeError::Disabled(disabled)),
+        }
+    }
+}
+
+impl<T> MemoryTracker<T> {
+    pub fn tracker_type(&self) -> TrackerType {
+        self.tracker_type
+    }
+
+    // The state is only reached after the scheme accepted the token, so that a
+    // foreign token cannot claim one of this tracker's slots
+    fn local(&self, token: &ThreadToken) -> &LocalState {
+        self.tracker.check_token(token);
+        self.locals.get_or_grow(token)
+    }
+
+    pub fn register(&self) -> ThreadToken {
+        let token = self.tracker.register();
+        self.local(&token).reset(self.slot_num);
+        if let Some(checker) = &self.checker {
+            checker.registered();
+        }
+        token
+    }
+
+    pub fn unregister(&self, token: ThreadToken) {
+        if let Some(checker) = &self.checker {
+            checker.unregistered();
+        }
+        self.tracker.unregister(token);
+    }
+
+    pub fn alloc(&self, obj: T, token: &ThreadToken) -> *mut T {
+        let obj = self.tracker.alloc(obj, token);
+        if let Some(checker) = &self.checker {
+            checker.allocated(obj as usize);
+        }
+        obj
+    }
+
+    /// Free an object that was never published.
+    ///
+    /// # Safety
+    /// `obj` must come from `alloc` of this tracker and no other thread may have seen it.
+    /// It is freed right away and must not be used or freed again.
+    pub unsafe fn reclaim(&self, obj: *mut T) {
+        self.tracker.reclaim(obj);
+    }
+
+    pub fn start_op(&self, token: &ThreadToken) {
+        self.tracker.start_op(token);
+    }
+
+    // The scheme drops every reservation of the operation. A thread whose retires
+    // went over the memory budget then waits as the budget policy says: only now,
+    // as it protects nothing any more, can waiting not deadlock. With the Error
+    // policy the excess is returned here and reported again by the next `admit`.
+    pub fn end_op(&self, token: &ThreadToken) -> Result<(), BudgetExceeded> {
+        self.tracker.end_op(token);
+        if self.local(token).overdrawn.replace(false) {
+            return self.tracker.admit(token);
+        }
+        Ok(())
+    }
+
+    // Load `obj` and protect what it points to in slot `idx`
+    pub fn read(&self, obj: &AtomicPtr<T>, idx: usize, token: &ThreadToken) -> *mut T {
+        let physical = self.local(token).physical(idx);
+        self.tracker.read(obj, physical, token)
+    }
+
+    // Protect a pointer the thread already holds in slot `idx`
+    pub fn reserve(&self, obj: *mut T, idx: usize, token: &ThreadToken) {
+        let physical = self.local(token).physical(idx);
+        self.tracker.reserve(obj, physical, token);
+    }
+
+    // Move the reservation in slot `src_idx` to slot `dst_idx`, e.g. cur to prev
+    // while walking a list hand over hand. The physical slots are swapped, so the
+    // object stays protected throughout and `src_idx` is free for the next read.
+    pub fn transfer(&self, src_idx: usize, dst_idx: usize, token: &ThreadToken) {
+        self.local(token).swap(src_idx, dst_idx);
+    }
+
+    pub fn release(&self, idx: usize, token: &ThreadToken) {
+        let physical = self.local(token).physical(idx);
+        self.tracker.release(physical, token);
+    }
+
+    pub fn clear_all(&self, token: &ThreadToken) {
+        self.tracker.clear_all(token);
+    }
+
+    /// Free `obj` once no thread can reach it any more. Panics in checking mode if
+    /// `obj` was already retired.
+    ///
+    /// # Safety
+    /// `obj` must come from `alloc` of this tracker, be unlinked from every shared
+    /// location and be retired only once.
+    pub unsafe fn retire(&self, obj: *mut T, token: &ThreadToken) {
+        if let Some(checker) = &self.checker {
+            checker.retired(obj as usize);
+        }
+        self.tracker.retire(obj, token);
+        self.charged(token);
+    }
+
+    // The budget is enforced at retire time: the retire itself already forced
+    // reclamation, the thread waits at the end of its operation
+    fn charged(&self, token: &ThreadToken) {
+        if self.tracker.over_budget() {
+            self.local(token).overdrawn.set(true);
+        }
+    }
+
+    /// Retire an object of another type than T, so that one tracker can protect
+    /// a structure with several node types. It is destroyed once every thread that
+    /// is inside an operation now has left it. Call it inside an operation.
+    ///
+    /// # Safety
+    /// `obj` must come from `Box::into_raw`, be unlinked from every shared location
+    /// and be retired only once.
+    pub unsafe fn retire_boxed<U: Send + 'static>(&self, obj: *mut U, token: &ThreadToken) {
+        if !obj.is_null() {
+            self.tracker.retire_foreign(Foreign::boxed(obj), token);
+            self.charged(token);
+        }
+    }
+
+    // Run `f` once every thread that is inside an operation now has left it, e.g.
+    // to free what a removed node points to. Call it inside an operation.
+    pub fn defer<F: FnOnce() + Send + 'static>(&self, f: F, token: &ThreadToken) {
+        self.tracker.retire_foreign(Foreign::closure(Box::new(f)), token);
+        self.charged(token);
+    }
+
+    // Whether the caller should walk its structure to check that a node is
+    // unlinked before retiring it: checking mode with a single thread registered
+    #[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+    pub(crate) fn checks_reachability(&self) -> bool {
+        self.checker.as_ref().is_some_and(|checker| checker.single_threaded())
+    }
+
+    pub fn get_retired_cnt(&self, token: &ThreadToken) -> u64 {
+        self.tracker.get_retired_cnt(token)
+    }
+
+    pub fn admit(&self, token: &ThreadToken) -> Result<(), BudgetExceeded> {
+        self.tracker.admit(token)
+    }
+
+    pub fn active_threads(&self) -> i64 {
+        self.tracker.active_threads()
+    }
+
+    pub fn drain(&mut self) {
+        self.tracker.drain();
+    }
+
+    #[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+    pub(crate) fn retirement_state(&self, token: &ThreadToken) -> Option<RetirementState> {
+        self.tracker.retirement_state(token)
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.checker {
+            Some(checker) => format!("{}, retire checks ({} retired nodes tracked)", self.tracker.describe(), checker.outstanding()),
+            None => self.tracker.describe(),
+        }
+    }
+
+    // Start an operation that ends when the guard is dropped
+    pub fn pin<'a>(&'a self, token: &'a ThreadToken) -> Guard<'a, T> {
+        self.start_op(token);
+        Guard { tracker: self, token }
+    }
+}
+
+// An operation of one thread on a tracker, between `pin` and the drop of the
+// guard. What the guard reads stays protected until then.
+pub struct Guard<'a, T> {
+    tracker: &'a MemoryTracker<T>,
+    token: &'a ThreadToken,
+}
+
+impl<T> Guard<'_, T> {
+    pub fn read(&self, obj: &AtomicPtr<T>, idx: usize) -> *mut T {
+        self.tracker.read(obj, idx, self.token)
+    }
+
+    pub fn reserve(&self, obj: *mut T, idx: usize) {
+        self.tracker.reserve(obj, idx, self.token);
+    }
+
+    pub fn transfer(&self, src_idx: usize, dst_idx: usize) {
+        self.tracker.transfer(src_idx, dst_idx, self.token);
+    }
+
+    pub fn release(&self, idx: usize) {
+        self.tracker.release(idx, self.token);
+    }
+
+    /// Retire `obj` from within the operation, see `MemoryTracker::retire`.
+    ///
+    /// # Safety
+    /// `obj` must come from `alloc` of this tracker, be unlinked from every shared
+    /// location and be retired only once.
+    pub unsafe fn retire(&self, obj: *mut T) {
+        unsafe { self.tracker.retire(obj, self.token) };
+    }
+
+    /// Retire an object of another type than T, see `MemoryTracker::retire_boxed`.
+    ///
+    /// # Safety
+    /// `obj` must come from `Box::into_raw`, be unlinked from every shared location
+    /// and be retired only once.
+    pub unsafe fn retire_boxed<U: Send + 'static>(&self, obj: *mut U) {
+        unsafe { self.tracker.retire_boxed(obj, self.token) };
+    }
+
+    // Run `f` once the threads inside an operation now have left it
+    pub fn defer<F: FnOnce() + Send + 'static>(&self, f: F) {
+        self.tracker.defer(f, self.token);
+    }
+
+    // End the operation, see `MemoryTracker::end_op`
+    pub fn end(self) -> Result<(), BudgetExceeded> {
+        let guard = ManuallyDrop::new(self);
+        guard.tracker.end_op(guard.token)
+    }
+}
+
+impl<T> Drop for Guard<'_, T> {
+    // A drop cannot fail, the next `admit` reports a budget this operation exceeded
+    fn drop(&mut self) {
+        let _ = self.tracker.end_op(self.token);
+    }
+}
+
+impl<T> RetiredMonitorable for MemoryTracker<T> {
+    fn collect_retired_stats(&self) -> Vec<(usize, RetiredStats)> {
+        self.tracker.collect_retired_stats()
+    }
+}
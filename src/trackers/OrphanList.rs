@@ -0,0 +1,59 @@
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+// Retired entries handed off by one exiting thread
+struct Orphans<E> {
+    entries: Vec<E>,
+    next: *mut Orphans<E>,
+}
+
+// Global list of retired entries whose thread unregistered before they could be
+// freed. Exiting threads push their leftovers, live threads adopt all of them at
+// their next scan. Entries are only ever taken all at once, so there is no ABA.
+pub(crate) struct OrphanList<E> {
+    head: AtomicPtr<Orphans<E>>,
+}
+
+unsafe impl<E> Send for OrphanList<E> {}
+unsafe impl<E> Sync for OrphanList<E> {}
+
+impl<E> OrphanList<E> {
+    pub(crate) fn new() -> Self {
+        OrphanList { head: AtomicPtr::new(null_mut()) }
+    }
+
+    pub(crate) fn push(&self, entries: Vec<E>) {
+        if entries.is_empty() {
+            return;
+        }
+        let orphans = Box::into_raw(Box::new(Orphans { entries, next: null_mut() }));
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            unsafe { (*orphans).next = head };
+            match self.head.compare_exchange(head, orphans, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => break,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    // Move every orphaned entry into `retired`
+    pub(crate) fn adopt(&self, retired: &mut Vec<E>) {
+        if self.head.load(Ordering::Relaxed).is_null() {
+            return;
+        }
+        let mut current = self.head.swap(null_mut(), Ordering::AcqRel);
+        while !current.is_null() {
+            let mut orphans = unsafe { Box::from_raw(current) };
+            retired.append(&mut orphans.entries);
+            current = orphans.next;
+        }
+    }
+}
+
+impl<E> Drop for OrphanList<E> {
+    fn drop(&mut self) {
+        // The tracker adopts everything before it goes away, only the list itself is left
+        self.adopt(&mut Vec::new());
+    }
+}
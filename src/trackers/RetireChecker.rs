@@ -0,0 +1,54 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// Independent locks the retired addresses are spread over
+const SHARDS: usize = 64;
+
+// Checking mode of the dispatcher: remembers every address retired since it was
+// last allocated, so a second retire of the same node is caught before it reaches
+// the scheme. An address leaves the set when the tracker hands it out again, a
+// recycled block is a new node.
+pub(crate) struct RetireChecker {
+    shards: Vec<Mutex<HashSet<usize>>>,
+    threads: AtomicUsize, // Registered threads, reachability is only checked when alone
+}
+
+impl RetireChecker {
+    pub(crate) fn new() -> Self {
+        RetireChecker { shards: (0..SHARDS).map(|_| Mutex::new(HashSet::new())).collect(), threads: AtomicUsize::new(0) }
+    }
+
+    fn shard(&self, addr: usize) -> &Mutex<HashSet<usize>> {
+        // Blocks are at least 8-byte aligned, the low bits carry no information
+        &self.shards[(addr >> 4) % SHARDS]
+    }
+
+    pub(crate) fn allocated(&self, addr: usize) {
+        self.shard(addr).lock().unwrap().remove(&addr);
+    }
+
+    pub(crate) fn retired(&self, addr: usize) {
+        let first = self.shard(addr).lock().unwrap().insert(addr);
+        assert!(first, "Node {:#x} retired twice", addr);
+    }
+
+    pub(crate) fn registered(&self) {
+        self.threads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn unregistered(&self) {
+        self.threads.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // Only one thread uses the tracker, so nobody can change the structure while
+    // the caller walks it
+    #[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+    pub(crate) fn single_threaded(&self) -> bool {
+        self.threads.load(Ordering::Relaxed) == 1
+    }
+
+    pub(crate) fn outstanding(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+}
@@ -0,0 +1,777 @@
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicPtr, AtomicU32, AtomicUsize, Ordering};
+use portable_atomic::AtomicI128;
+use std::alloc::Layout;
+use std::mem::{offset_of, size_of};
+use std::ptr::null_mut;
+use crate::trackers::BaseTracker::{BaseTracker, Foreign};
+use crate::util::Backoff::BackoffStrategy;
+use crate::util::MemoryBudget::{BudgetExceeded, MemoryBudget};
+use crate::util::Quarantine::SystemRelease;
+use crate::util::RAllocator::RAllocator;
+use crate::util::RetiredMonitorable::{RetiredCounters, RetiredMonitorable, RetiredStats};
+#[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+use crate::util::StateExport::{RetiredEntryState, RetirementListState, RetirementState};
+use crate::util::ThreadRegistry::{ThreadRegistry, ThreadToken};
+
+
+
+// The reclamation header lives in front of the object, in the block the tracker
+// allocates, so retiring an object never touches its fields: readers that are
+// still traversing a retired node keep following its `next` until it is freed.
+#[repr(C)]
+struct Block<T> {
+    hdr: Retired, // Must stay the first field, the retirement list only sees the header
+    obj: T,
+}
+
+// Layout of the blocks the tracker allocates for objects of type T, the
+// tracker's allocator hands out blocks of exactly this layout
+pub(crate) fn block_layout<T>() -> Layout {
+    Layout::new::<Block<T>>()
+}
+
+impl<T> Block<T> {
+    fn alloc(obj: T, allocator: &dyn RAllocator, token: &ThreadToken) -> *mut T {
+        let ptr = allocator.alloc_block(token) as *mut Block<T>;
+        unsafe {
+            ptr.write(Block { hdr: Retired::new(Block::<T>::free), obj });
+            &mut (*ptr).obj
+        }
+    }
+
+    // The block an object handed out by `alloc` lives in
+    fn of(obj: *mut T) -> *mut Block<T> {
+        unsafe { (obj as *mut u8).sub(offset_of!(Block<T>, obj)) as *mut Block<T> }
+    }
+
+    // Destroy the object owning the header, returns the number of bytes released
+    unsafe fn free(hdr: *mut Retired, tracker: &MemoryTracker, token: Option<&ThreadToken>) -> usize {
+        std::ptr::drop_in_place(hdr as *mut Block<T>);
+        tracker.allocator.free_block(hdr as *mut u8, token);
+        size_of::<Block<T>>()
+    }
+}
+
+// Reclamation header of a retired object. The header does not know the type of
+// the object it belongs to, only how to destroy it, so one tracker can retire
+// nodes of different types as well as arbitrary deferred closures.
+pub(crate) struct Retired {
+    nref: AtomicI64,                  // Threads that still have to traverse this entry
+    next: AtomicPtr<Retired>,         // Next-retired: the entry inserted right before this one
+    batch_link: AtomicPtr<Retired>,   // Next member of the batch this entry heads
+    batch_refs: *mut Retired,         // Hyaline-1: head of the batch, its nref counts for every member
+    owner: *const RetiredCounters,    // Counters of the thread that retired the entry
+    drop_fn: DropFn,                  // Destroys and frees the object owning the header
+}
+
+// Destroys the object owning a header. Blocks go back to the tracker's allocator,
+// on the freeing thread's list if it is known.
+type DropFn = unsafe fn(*mut Retired, &MemoryTracker, Option<&ThreadToken>) -> usize;
+
+impl Retired {
+    fn new(drop_fn: DropFn) -> Retired {
+        Retired {
+            nref: AtomicI64::new(0),
+            next: AtomicPtr::new(null_mut()),
+            batch_link: AtomicPtr::new(null_mut()),
+            batch_refs: null_mut(),
+            owner: std::ptr::null(),
+            drop_fn,
+        }
+    }
+
+    // Hyaline-1 link for a batch with fewer members than active threads. It
+    // stands for no object and has no owner, so it is neither charged nor counted.
+    fn filler() -> *mut Retired {
+        Box::into_raw(Box::new(Retired::new(Retired::free_filler)))
+    }
+
+    unsafe fn free_filler(hdr: *mut Retired, _tracker: &MemoryTracker, _token: Option<&ThreadToken>) -> usize {
+        drop(Box::from_raw(hdr));
+        0
+    }
+
+    // The entry as it appears in a snapshot, with the other members of its batch
+    #[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+    unsafe fn state(entry: *mut Retired) -> RetiredEntryState {
+        let mut batch = Vec::new();
+        let mut member = (*entry).batch_link.load(Ordering::Acquire);
+        while !member.is_null() {
+            batch.push(member as usize);
+            member = (*member).batch_link.load(Ordering::Acquire);
+        }
+        RetiredEntryState { addr: entry as usize, nref: (*entry).nref.load(Ordering::Acquire), batch }
+    }
+
+    // Destroy every object of the batch headed by `entry` and account for them
+    // on the thread that retired the batch, returns the bytes released
+    unsafe fn free(entry: *mut Retired, tracker: &MemoryTracker, token: Option<&ThreadToken>) -> u64 {
+        let owner = (*entry).owner;
+        let mut count = 0;
+        let mut bytes = 0;
+        let mut current = entry;
+        while !current.is_null() {
+            let next = (*current).batch_link.load(Ordering::Acquire);
+            if !(*current).owner.is_null() {
+                count += 1;
+            }
+            bytes += ((*current).drop_fn)(current, tracker, token);
+            current = next;
+        }
+        if !owner.is_null() {
+            (*owner).collect_reclaimed_size(count, bytes as u64);
+        }
+        bytes as u64
+    }
+}
+
+// Header for what the tracker did not allocate: objects of other types and
+// closures, see `retire_foreign`
+#[repr(C)]
+struct Deferred {
+    hdr: Retired,
+    foreign: Foreign,
+}
+
+impl Deferred {
+    fn alloc(foreign: Foreign) -> *mut Retired {
+        Box::into_raw(Box::new(Deferred { hdr: Retired::new(Deferred::free), foreign })) as *mut Retired
+    }
+
+    unsafe fn free(hdr: *mut Retired, tracker: &MemoryTracker, _token: Option<&ThreadToken>) -> usize {
+        let deferred = Box::from_raw(hdr as *mut Deferred);
+        size_of::<Deferred>() + deferred.foreign.free(&tracker.foreign) as usize
+    }
+}
+
+// when a thread entries, we need to return snapshot of the head
+#[derive(Default)]
+pub(crate) struct Handle {
+    nref: AtomicI64,          // HRef after this thread entered
+    seq: AtomicU32,           // Number of entries retired before this thread entered
+    next: AtomicPtr<Retired>, // HPtr when this thread entered
+}
+
+impl Handle {
+    #[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+    fn new(nref: i64, seq: u32, next: *mut Retired) -> Self{
+        Handle{
+            nref: AtomicI64::new(nref),
+            seq: AtomicU32::new(seq),
+            next: AtomicPtr::new(next),
+        }
+    }
+}
+
+// Per-thread state of a thread registered with the tracker. Only the owning
+// thread touches it, except for the batch that a thread helping a tracker over
+// its budget may take; the other atomics just make the slot shareable. The handle
+// snapshot is kept inline, so enter/leave never allocate, and the slot is
+// padded to a cache line so threads do not false-share their snapshots.
+#[derive(Default)]
+#[repr(align(128))]
+pub(crate) struct LocalState {
+    handle: Handle,             // Snapshot of the head taken by the current operation
+    in_op: AtomicBool,          // Between enter and leave, a second leave must not touch HRef
+    batch: AtomicPtr<Retired>,  // Entries retired locally, chained through batch_link
+    batch_len: AtomicUsize,     // Number of entries in `batch`
+    slot_head: AtomicUsize,     // Hyaline-1 only: | HPtr: 63 | HRef: 1 |, HPtr is a batch member
+    stats: RetiredCounters,
+}
+
+// Which head the retirement list uses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum HeadLayout {
+    // One list for all threads, headed by a 128-bit | HRef | Seq | HPtr | word
+    Wide,
+    // Hyaline-1: one list per thread, headed by a 64-bit word in the thread's slot.
+    // The thread is the only one counted in its list, so HRef shrinks to an active
+    // bit and no Seq is needed: leave detaches the whole list, which holds exactly
+    // the entries retired while the thread was active. Each member of a batch links
+    // the batch into the list of one active thread through its own header, so a
+    // batch has at least one member per registered thread. Retiring costs one CAS
+    // per active thread instead of one in total.
+    PerThread,
+}
+
+// Whether the 128-bit head is updated with a native double-width CAS. If not,
+// portable_atomic falls back to a lock and the Wide layout measures a spinlock.
+pub fn wide_head_lock_free() -> bool {
+    AtomicI128::is_lock_free()
+}
+
+// Marks a Hyaline-1 slot whose thread is between enter and leave
+const SLOT_ACTIVE: usize = 1;
+
+// Head struct holds the retirement list's head pointer and the active thread counter (HRef).
+// The 128-bit word is laid out as | HRef: 32 | Seq: 32 | HPtr: 64 |. Seq counts every
+// insertion into the list, so a leaving thread knows exactly how many entries were
+// retired while it was active and never has to compare against a possibly freed HPtr.
+struct AtomicHead {
+    href: AtomicI128, // Atomic I128 for lock-free updates of href and hptr together
+}
+
+impl AtomicHead {
+    fn new(href: AtomicI128) -> Self {
+        AtomicHead {
+            href, // Initialize HRef and HPtr to 0
+        }
+    }
+
+    fn pack(href: i64, seq: u32, hptr: *mut Retired) -> i128 {
+        ((href as i128) << 96) | ((seq as i128) << 64) | (hptr as usize as i128)
+    }
+
+    fn unpack(value: i128) -> (i64, u32, *mut Retired) {
+        ((value >> 96) as i64, (value >> 64) as u32, value as u64 as usize as *mut Retired)
+    }
+
+    // Before return, change head to handle
+    #[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+    pub(crate) fn head_to_handle(&self) -> Handle {
+        let (nref, seq, hptr) = AtomicHead::unpack(self.href.load(Ordering::Acquire));
+        Handle::new(nref, seq, hptr)
+    }
+}
+
+// MemoryTracker holds the global retirement list and provides methods for interacting with it
+pub(crate) struct MemoryTracker {
+    head: AtomicHead, // Arc to share the Head structure across threads
+    threads: ThreadRegistry<LocalState>,
+    batch_size: usize,    // Entries retired locally before the batch is inserted into the list
+    budget: Option<MemoryBudget>, // Cap on retired but not yet reclaimed bytes
+    allocator: Box<dyn RAllocator>, // Blocks of `block_layout`
+    foreign: SystemRelease, // Memory of retired objects the tracker did not allocate
+    layout: HeadLayout,
+    backoff: BackoffStrategy, // Applied to every retried CAS on a head
+}
+
+impl MemoryTracker
+{
+    // Create a new MemoryTracker instance. Retired entries are collected in
+    // per-thread batches of `batch_size` and each batch is inserted into the
+    // retirement list with one CAS and a single reference count. With a budget
+    // the garbage held by the tracker is kept under its cap, see `admit`. The
+    // allocator must hand out blocks of `block_layout::<T>()` for the T allocated.
+    // In use-after-free mode `quarantine` blocks of foreign objects are held back.
+    pub(crate) fn new(batch_size: usize, budget: Option<MemoryBudget>, allocator: Box<dyn RAllocator>,
+                      quarantine: usize, layout: HeadLayout, backoff: BackoffStrategy) -> Self {
+        MemoryTracker {
+            head: AtomicHead::new(AtomicI128::new(0)),
+            threads: ThreadRegistry::new(),
+            batch_size: batch_size.max(1),
+            budget,
+            allocator,
+            foreign: SystemRelease::new(quarantine),
+            layout,
+            backoff,
+        }
+    }
+
+    // Called outside of enter/leave. If the garbage is over budget the pending
+    // batches are flushed first (nothing in them is protected by a thread that
+    // is not counted at insertion, so they are freed as soon as the threads
+    // counted in them leave), then the budget policy decides whether to wait or
+    // fail. Waiting inside an operation could deadlock: every entry inserted while
+    // a thread is active counts that thread, so only leaving lets the garbage drop.
+    pub(crate) fn admit(&self, token: &ThreadToken) -> Result<(), BudgetExceeded> {
+        match &self.budget {
+            Some(budget) => budget.wait(|| self.help(token)),
+            None => Ok(()),
+        }
+    }
+
+    // Flush our batch, then the batches of the other threads: an idle thread may
+    // never retire again to fill its own
+    fn help(&self, token: &ThreadToken) {
+        self.flush(token);
+        self.threads.for_each(|_, other| {
+            let batch = other.batch.swap(null_mut(), Ordering::Acquire);
+            if !batch.is_null() {
+                // Racing with the owner, the count may end up a little off: it only
+                // decides when the owner flushes
+                other.batch_len.store(0, Ordering::Relaxed);
+                self.push_retired(batch, token);
+            }
+        });
+    }
+
+    unsafe fn reclaim(&self, entry: *mut Retired, token: Option<&ThreadToken>) {
+        let bytes = Retired::free(entry, self, token);
+        if let Some(budget) = &self.budget {
+            budget.credit(bytes);
+        }
+    }
+
+    // Claim a slot for the calling thread
+    pub(crate) fn register(&self) -> ThreadToken {
+        self.threads.register()
+    }
+
+    // Flush the thread's partial batch and give its slot back
+    pub(crate) fn unregister(&self, token: ThreadToken) {
+        self.flush(&token);
+        self.threads.unregister(token);
+    }
+
+    // Number of threads currently between enter and leave
+    pub(crate) fn active_threads(&self) -> i64 {
+        if self.layout == HeadLayout::PerThread {
+            let mut active = 0;
+            self.threads.for_each(|_, local| active += (local.slot_head.load(Ordering::Acquire) & SLOT_ACTIVE) as i64);
+            return active;
+        }
+        AtomicHead::unpack(self.head.href.load(Ordering::Acquire)).0
+    }
+
+    // Snapshot of the retirement lists and the local batches as the calling thread
+    // sees them from inside an operation, for debugging. Every entry inserted
+    // since the thread entered counts it and stays alive until it leaves, those
+    // are listed with their NRef; older ones may already be freed and are left
+    // out. Hyaline-1 counts a thread only in its own list, the other lists are
+    // shown by their head alone. Batches not inserted yet are inserted while the
+    // thread is inside, so they count it too and can be walked.
+    #[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+    pub(crate) fn retirement_state(&self, token: &ThreadToken) -> RetirementState {
+        assert!(self.in_op(token), "retirement_state outside of enter/leave");
+        let local = self.threads.get(token);
+        let mut lists = Vec::new();
+        match self.layout {
+            HeadLayout::Wide => {
+                let handle = self.head.head_to_handle();
+                let seq = handle.seq.load(Ordering::Relaxed);
+                let hptr = handle.next.load(Ordering::Relaxed);
+                let mut alive = seq.wrapping_sub(local.handle.seq.load(Ordering::Relaxed));
+                let mut entries = Vec::new();
+                let mut current = hptr;
+                while alive > 0 && !current.is_null() {
+                    entries.push(unsafe { Retired::state(current) });
+                    current = unsafe { (*current).next.load(Ordering::Acquire) };
+                    alive -= 1;
+                }
+                lists.push(RetirementListState {
+                    name: "head".to_string(),
+                    href: handle.nref.load(Ordering::Relaxed),
+                    seq: Some(seq),
+                    hptr: hptr as usize,
+                    entries,
+                    truncated: !current.is_null(),
+                });
+            }
+            HeadLayout::PerThread => {
+                self.threads.for_each(|tid, other| {
+                    let head = other.slot_head.load(Ordering::Acquire);
+                    if head == 0 {
+                        return; // Idle slot, nothing to show
+                    }
+                    // A member stands for its batch, which holds the reference
+                    let own = std::ptr::eq(other, local);
+                    let mut entries = Vec::new();
+                    let mut member = (head & !SLOT_ACTIVE) as *mut Retired;
+                    while own && !member.is_null() {
+                        unsafe {
+                            entries.push(Retired::state((*member).batch_refs));
+                            member = (*member).next.load(Ordering::Acquire);
+                        }
+                    }
+                    lists.push(RetirementListState {
+                        name: format!("slot {}", tid),
+                        href: (head & SLOT_ACTIVE) as i64,
+                        seq: None,
+                        hptr: head & !SLOT_ACTIVE,
+                        entries,
+                        truncated: !member.is_null(),
+                    });
+                });
+            }
+        }
+        let mut pending = Vec::new();
+        self.threads.for_each(|tid, other| {
+            let mut batch = Vec::new();
+            let mut current = other.batch.load(Ordering::Acquire);
+            while !current.is_null() {
+                batch.push(current as usize);
+                current = unsafe { (*current).batch_link.load(Ordering::Acquire) };
+            }
+            if !batch.is_empty() {
+                pending.push((tid, batch));
+            }
+        });
+        RetirementState { lists, pending }
+    }
+
+    // Atomically increment HRef and keep a snapshot of HPtr in the thread's slot
+    pub(crate) fn enter(&self, token: &ThreadToken) {
+        if self.layout == HeadLayout::PerThread {
+            // The list is empty since the last leave, only the active bit is set
+            self.threads.get(token).slot_head.store(SLOT_ACTIVE, Ordering::SeqCst);
+            return;
+        }
+        let local = self.threads.get(token);
+        let handle = &local.handle;
+        let mut backoff = self.backoff.start();
+        loop {
+            // Get the current head
+            let current = self.head.href.load(Ordering::Acquire);
+            let (href, seq, hptr) = AtomicHead::unpack(current);
+
+            // Try to atomically increment HRef and keep HPtr unchanged
+            let new_value = AtomicHead::pack(href + 1, seq, hptr);
+
+            // Perform a CAS operation to update the HRef and HPtr together using compare_exchange
+            if self.head.href.compare_exchange(
+                current,      // expected value
+                new_value,    // new value
+                Ordering::AcqRel, // success ordering
+                Ordering::Acquire, // failure ordering
+            ).is_ok() {
+                // Successfully updated, record the snapshot
+                handle.nref.store(href + 1, Ordering::Relaxed);
+                handle.seq.store(seq, Ordering::Relaxed);
+                handle.next.store(hptr, Ordering::Relaxed);
+                local.in_op.store(true, Ordering::Relaxed);
+                break;
+            }
+            backoff.failed();
+        }
+    }
+
+    // Leave operation: decrement HRef and clean up any nodes if necessary
+    pub(crate) fn leave(&self, token: &ThreadToken) {
+        if self.layout == HeadLayout::PerThread {
+            return self.leave_slot(token);
+        }
+        let local = self.threads.get(token);
+        if !local.in_op.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let enter_seq = local.handle.seq.load(Ordering::Relaxed);
+        let mut backoff = self.backoff.start();
+        let (leave_seq, mut current) = loop {
+            // get the current head
+            let current = self.head.href.load(Ordering::Acquire);
+            let (href, seq, hptr) = AtomicHead::unpack(current);
+
+            // Decrement HRef to indicate thread leaving. The last thread to leave
+            // detaches the list; every entry in it is owned by the leaving threads.
+            let new_hptr = if href == 1 { null_mut() } else { hptr };
+            let new_value = AtomicHead::pack(href - 1, seq, new_hptr);
+
+            // Perform a CAS operation to update HRef and HPtr together using compare_exchange
+            if self.head.href.compare_exchange(
+                current,      // expected value
+                new_value,    // new value
+                Ordering::AcqRel, // success ordering
+                Ordering::Acquire, // failure ordering
+            ).is_ok() {
+                break (seq, hptr); // Successfully updated, exit the loop
+            }
+            backoff.failed();
+        };
+        // Every entry retired between our enter and our leave counted us in its NRef.
+        // Traverse exactly those entries, from the current HPtr down to our handle.
+        let mut remaining = leave_seq.wrapping_sub(enter_seq);
+        while remaining > 0 {
+            unsafe {
+                let current_node = &*current;
+                // Read the next entry before our decrement may free this one
+                let next_node = current_node.next.load(Ordering::Acquire);
+                // If NRef reaches 0, deallocate the node
+                if current_node.nref.fetch_sub(1, Ordering::AcqRel) == 1 {
+                    self.reclaim(current, Some(token));
+                }
+                current = next_node;
+            }
+            remaining -= 1;
+        }
+    }
+
+    // Retire what the tracker did not allocate, a header is allocated for it.
+    // It is freed once the threads active now have left, like every other entry.
+    pub(crate) fn retire_foreign(&self, token: &ThreadToken, foreign: Foreign) {
+        assert!(self.in_op(token), "retire_foreign outside of enter/leave");
+        let bytes = size_of::<Deferred>() as u64 + foreign.size();
+        self.add_to_batch(token, Deferred::alloc(foreign), bytes as usize);
+    }
+
+    // Whether the thread is between enter and leave
+    fn in_op(&self, token: &ThreadToken) -> bool {
+        let local = self.threads.get(token);
+        match self.layout {
+            HeadLayout::Wide => local.in_op.load(Ordering::Relaxed),
+            HeadLayout::PerThread => local.slot_head.load(Ordering::Relaxed) & SLOT_ACTIVE != 0,
+        }
+    }
+
+    // Add an entry to the thread's local batch and insert the batch into the
+    // retirement list once it is full
+    fn add_to_batch(&self, token: &ThreadToken, entry: *mut Retired, bytes: usize) {
+        let local = self.threads.get(token);
+        local.stats.collect_retired_size(bytes as u64);
+        if let Some(budget) = &self.budget {
+            budget.charge(bytes as u64);
+        }
+        unsafe { (*entry).owner = &local.stats };
+        // A helping thread may take the batch at any time, see `help`
+        let mut batch = local.batch.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*entry).batch_link.store(batch, Ordering::Relaxed) };
+            match local.batch.compare_exchange(batch, entry, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(actual) => batch = actual,
+            }
+        }
+        // Hyaline-1 waits for a member per registered thread, so that no filler is
+        // needed. Forced reclamation: over budget, do not hold garbage back in a
+        // partial batch.
+        let full = match self.layout {
+            HeadLayout::Wide => self.batch_size,
+            HeadLayout::PerThread => self.batch_size.max(self.threads.registered()),
+        };
+        if local.batch_len.fetch_add(1, Ordering::Relaxed) + 1 >= full
+            || self.budget.as_ref().is_some_and(|budget| budget.exceeded()) {
+            self.flush(token);
+        }
+    }
+
+    // Insert the local batch into the retirement list. Threads that were active when
+    // an entry was retired are still active now or have left, so counting the
+    // threads active at insertion is enough to protect every entry of the batch.
+    fn flush(&self, token: &ThreadToken) {
+        let local = self.threads.get(token);
+        let batch = local.batch.swap(null_mut(), Ordering::Acquire);
+        local.batch_len.store(0, Ordering::Relaxed);
+        if !batch.is_null() {
+            self.push_retired(batch, token);
+        }
+    }
+
+    fn push_retired(&self, new_node: *mut Retired, token: &ThreadToken) {
+        let href = match self.layout {
+            HeadLayout::Wide => self.push_to_head(new_node),
+            HeadLayout::PerThread => self.push_to_slots(new_node),
+        };
+        if href == 0 {
+            // Nobody can hold a reference to the object, free it right away
+            unsafe { self.reclaim(new_node, Some(token)) };
+            return;
+        }
+
+        // "adjust" mentioned in the paper. new_node.nref add the HRef seen at insertion,
+        // i.e. the number of threads that will traverse the new node when they leave.
+        // Leaving threads may already have decremented it, whoever reaches 0 frees it.
+        unsafe {
+            if (*new_node).nref.fetch_add(href, Ordering::AcqRel) + href == 0 {
+                self.reclaim(new_node, Some(token));
+            }
+        }
+    }
+
+    // Insert the entry at the head of the shared list, returns the HRef it saw
+    fn push_to_head(&self, new_node: *mut Retired) -> i64 {
+        let mut backoff = self.backoff.start();
+        loop {
+            // Get the head
+            let current = self.head.href.load(Ordering::Acquire);
+            let (href, seq, hptr) = AtomicHead::unpack(current);
+            if href == 0 {
+                break 0;
+            }
+            // The head point to the new node so the new node is inserted after the head
+            // new_node.next point to the node that the old head point to
+            unsafe { (*new_node).next.store(hptr, Ordering::Relaxed) };
+
+            let new_head = AtomicHead::pack(href, seq.wrapping_add(1), new_node);
+
+            // Use CAS to update the head
+            if self.head.href.compare_exchange(
+                current,
+                new_head,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ).is_ok() {
+                break href;
+            }
+            backoff.failed();
+        }
+    }
+}
+
+// Hyaline-1
+impl MemoryTracker {
+    // Detach the thread's list and clear the active bit in one swap, then drop the
+    // reference the thread holds on the batch of every member in it
+    fn leave_slot(&self, token: &ThreadToken) {
+        let head = self.threads.get(token).slot_head.swap(0, Ordering::SeqCst);
+        let mut member = (head & !SLOT_ACTIVE) as *mut Retired;
+        while !member.is_null() {
+            unsafe {
+                // Read the link before our decrement may free the batch holding it
+                let next = (*member).next.load(Ordering::Acquire);
+                let batch = (*member).batch_refs;
+                if (*batch).nref.fetch_sub(1, Ordering::AcqRel) == 1 {
+                    self.reclaim(batch, Some(token));
+                }
+                member = next;
+            }
+        }
+    }
+
+    // Insert the batch into the list of every active thread, one member per list,
+    // returns how many lists it went into. A thread that leaves before the CAS is
+    // skipped: it cannot reach the batch any more. Should more threads be active
+    // than the batch has members, fillers are appended to it.
+    fn push_to_slots(&self, batch: *mut Retired) -> i64 {
+        let mut inserted = 0;
+        let mut last: *mut Retired = null_mut(); // Member linked last, the next one follows it
+        self.threads.for_each(|_, local| {
+            let mut head = local.slot_head.load(Ordering::SeqCst);
+            let mut backoff = self.backoff.start();
+            while head & SLOT_ACTIVE != 0 {
+                let member = unsafe {
+                    let next = if last.is_null() { batch } else { (*last).batch_link.load(Ordering::Relaxed) };
+                    if next.is_null() {
+                        let filler = Retired::filler();
+                        (*last).batch_link.store(filler, Ordering::Relaxed);
+                        filler
+                    } else {
+                        next
+                    }
+                };
+                unsafe {
+                    (*member).batch_refs = batch;
+                    (*member).next.store((head & !SLOT_ACTIVE) as *mut Retired, Ordering::Relaxed);
+                }
+                match local.slot_head.compare_exchange(head, member as usize | SLOT_ACTIVE,
+                                                       Ordering::SeqCst, Ordering::SeqCst) {
+                    Ok(_) => {
+                        inserted += 1;
+                        last = member;
+                        break;
+                    }
+                    Err(actual) => {
+                        head = actual;
+                        backoff.failed();
+                    }
+                }
+            }
+        });
+        inserted
+    }
+}
+
+impl MemoryTracker {
+    // Free everything the tracker still holds once nobody is active: local batches
+    // of threads that never unregistered and whatever is still linked
+    // (the last leaving thread normally detaches the list). Entries counted by a
+    // thread that never left cannot be reached safely and leak.
+    pub(crate) fn drain(&mut self) {
+        let (href, _, mut current) = AtomicHead::unpack(*self.head.href.get_mut());
+        if href != 0 || self.active_threads() != 0 {
+            return;
+        }
+        self.threads.for_each(|_, local| {
+            let batch = local.batch.swap(null_mut(), Ordering::Relaxed);
+            local.batch_len.store(0, Ordering::Relaxed);
+            if !batch.is_null() {
+                unsafe { self.reclaim(batch, None) };
+            }
+        });
+        while !current.is_null() {
+            unsafe {
+                let next = (*current).next.load(Ordering::Acquire);
+                self.reclaim(current, None);
+                current = next;
+            }
+        }
+        *self.head.href.get_mut() = 0;
+    }
+}
+
+impl RetiredMonitorable for MemoryTracker {
+    fn collect_retired_stats(&self) -> Vec<(usize, RetiredStats)> {
+        let mut result = Vec::new();
+        self.threads.for_each(|tid, local| result.push((tid, local.stats.report_retired())));
+        result
+    }
+}
+
+impl Drop for MemoryTracker {
+    fn drop(&mut self) {
+        self.drain();
+    }
+}
+
+impl<T: Send> BaseTracker<T> for MemoryTracker {
+    fn register(&self) -> ThreadToken {
+        MemoryTracker::register(self)
+    }
+
+    fn unregister(&self, token: ThreadToken) {
+        MemoryTracker::unregister(self, token);
+    }
+
+    fn check_token(&self, token: &ThreadToken) {
+        self.threads.check(token);
+    }
+
+    fn alloc(&self, obj: T, token: &ThreadToken) -> *mut T {
+        self.threads.get(token).stats.collect_alloc(size_of::<Block<T>>() as u64);
+        Block::alloc(obj, self.allocator.as_ref(), token)
+    }
+
+    fn reclaim(&self, obj: *mut T) {
+        unsafe { Block::<T>::free(Block::of(obj) as *mut Retired, self, None) };
+    }
+
+    fn start_op(&self, token: &ThreadToken) {
+        self.enter(token);
+    }
+
+    fn end_op(&self, token: &ThreadToken) {
+        self.leave(token);
+    }
+
+    fn retire(&self, obj: *mut T, token: &ThreadToken) {
+        self.add_to_batch(token, Block::of(obj) as *mut Retired, size_of::<Block<T>>());
+    }
+
+    fn retire_foreign(&self, foreign: Foreign, token: &ThreadToken) {
+        MemoryTracker::retire_foreign(self, token, foreign);
+    }
+
+    // Hyaline keeps no per-thread list, count what the thread retired and nobody freed yet
+    fn get_retired_cnt(&self, token: &ThreadToken) -> u64 {
+        self.threads.get(token).stats.report_retired().unreclaimed()
+    }
+
+    fn admit(&self, token: &ThreadToken) -> Result<(), BudgetExceeded> {
+        MemoryTracker::admit(self, token)
+    }
+
+    fn over_budget(&self) -> bool {
+        self.budget.as_ref().is_some_and(|budget| budget.exceeded())
+    }
+
+    fn active_threads(&self) -> i64 {
+        MemoryTracker::active_threads(self)
+    }
+
+    fn drain(&mut self) {
+        MemoryTracker::drain(self);
+    }
+
+    #[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+    fn retirement_state(&self, token: &ThreadToken) -> Option<RetirementState> {
+        Some(MemoryTracker::retirement_state(self, token))
+    }
+
+    fn describe(&self) -> String {
+        let head = match self.layout {
+            HeadLayout::Wide if wide_head_lock_free() => "128-bit lock-free",
+            HeadLayout::Wide => "128-bit lock-based",
+            HeadLayout::PerThread => "64-bit per thread",
+        };
+        format!("batch size {}, head {}, backoff {}, allocator {}", self.batch_size, head, self.backoff, self.allocator.describe())
+    }
+}
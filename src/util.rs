@@ -0,0 +1,18 @@
+// Only the utilities that are ported to the Rust harness are built
+#[allow(non_snake_case)]
+pub(crate) mod RetiredMonitorable;
+#[allow(non_snake_case)]
+pub(crate) mod MemoryBudget;
+#[allow(non_snake_case)]
+pub(crate) mod ThreadRegistry;
+#[allow(non_snake_case)]
+pub(crate) mod RAllocator;
+#[allow(non_snake_case)]
+pub(crate) mod Backoff;
+#[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+#[allow(non_snake_case)]
+pub(crate) mod KeyHasher;
+#[allow(non_snake_case)]
+pub(crate) mod Quarantine;
+#[allow(non_snake_case)]
+pub(crate) mod StateExport;
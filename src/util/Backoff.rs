@@ -0,0 +1,79 @@
+use std::fmt;
+
+// Spins an exponential backoff waits at most, unless the run sets its own cap
+const DEFAULT_MAX_SPINS: u32 = 1024;
+// Failed attempts before a yielding backoff gives up the CPU, unless the run sets its own
+const DEFAULT_YIELD_AFTER: u32 = 8;
+
+// What a thread does after a failed CAS before it retries
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BackoffStrategy {
+    #[default]
+    None,                           // Retry right away
+    Exponential { max_spins: u32 }, // Spin twice as long after every failure, up to `max_spins`
+    Yield { after: u32 },           // Spin once per failure, yield the thread from the `after`th on
+}
+
+impl BackoffStrategy {
+    // "none", "exp" or "exp:<max spins>", "yield" or "yield:<failures>"
+    pub fn from_name(name: &str) -> Option<BackoffStrategy> {
+        let (kind, param) = match name.split_once(':') {
+            Some((kind, param)) => (kind, Some(param.parse().ok()?)),
+            None => (name, None),
+        };
+        match kind {
+            "none" if param.is_none() => Some(BackoffStrategy::None),
+            "exp" => Some(BackoffStrategy::Exponential { max_spins: param.unwrap_or(DEFAULT_MAX_SPINS).max(1) }),
+            "yield" => Some(BackoffStrategy::Yield { after: param.unwrap_or(DEFAULT_YIELD_AFTER) }),
+            _ => None,
+        }
+    }
+
+    // State for one retry loop
+    #[cfg(any(feature = "hyaline", feature = "unordered-map", feature = "split-ordered-map"))]
+    pub(crate) fn start(self) -> Backoff {
+        Backoff { strategy: self, failures: 0 }
+    }
+}
+
+impl fmt::Display for BackoffStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackoffStrategy::None => write!(f, "none"),
+            BackoffStrategy::Exponential { max_spins } => write!(f, "exponential up to {} spins", max_spins),
+            BackoffStrategy::Yield { after } => write!(f, "yield after {} failures", after),
+        }
+    }
+}
+
+// Backoff of a single retry loop, created before the first attempt and told
+// about every failed one
+#[cfg(any(feature = "hyaline", feature = "unordered-map", feature = "split-ordered-map"))]
+pub(crate) struct Backoff {
+    strategy: BackoffStrategy,
+    failures: u32,
+}
+
+#[cfg(any(feature = "hyaline", feature = "unordered-map", feature = "split-ordered-map"))]
+impl Backoff {
+    // Wait according to the strategy after a failed attempt
+    pub(crate) fn failed(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+        match self.strategy {
+            BackoffStrategy::None => {}
+            BackoffStrategy::Exponential { max_spins } => {
+                let spins = 1u32.checked_shl(self.failures - 1).unwrap_or(u32::MAX).min(max_spins);
+                for _ in 0..spins {
+                    std::hint::spin_loop();
+                }
+            }
+            BackoffStrategy::Yield { after } => {
+                if self.failures >= after {
+                    std::thread::yield_now();
+                } else {
+                    std::hint::spin_loop();
+                }
+            }
+        }
+    }
+}
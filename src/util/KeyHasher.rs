@@ -0,0 +1,118 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+// 2^64 divided by the golden ratio, odd, spreads consecutive keys over the word
+const FIBONACCI: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// How a map turns a key into a bucket index
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum HasherType {
+    #[default]
+    SipHash,        // The standard library's DefaultHasher, robust but the slowest
+    Multiplicative, // Fibonacci hashing, one multiply per word of the key
+    Identity,       // The key itself, only for keys of a single integer; consecutive keys land in consecutive buckets
+}
+
+impl HasherType {
+    pub fn from_name(name: &str) -> Option<HasherType> {
+        match name {
+            "siphash" => Some(HasherType::SipHash),
+            "mult" => Some(HasherType::Multiplicative),
+            "identity" => Some(HasherType::Identity),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn hash<K: Hash>(self, key: &K) -> u64 {
+        let mut hasher = match self {
+            HasherType::SipHash => KeyHasher::Sip(DefaultHasher::new()),
+            HasherType::Multiplicative => KeyHasher::Multiplicative(0),
+            HasherType::Identity => KeyHasher::Identity(None),
+        };
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl fmt::Display for HasherType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HasherType::SipHash => write!(f, "siphash"),
+            HasherType::Multiplicative => write!(f, "multiplicative"),
+            HasherType::Identity => write!(f, "identity"),
+        }
+    }
+}
+
+enum KeyHasher {
+    Sip(DefaultHasher),
+    Multiplicative(u64),
+    Identity(Option<u64>),
+}
+
+impl Hasher for KeyHasher {
+    fn finish(&self) -> u64 {
+        match self {
+            KeyHasher::Sip(hasher) => hasher.finish(),
+            // The high bits are the well mixed ones, a bucket index takes the low ones
+            KeyHasher::Multiplicative(state) => state ^ (state >> 32),
+            KeyHasher::Identity(state) => state.expect("the identity hasher only takes integer keys"),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            KeyHasher::Sip(hasher) => hasher.write(bytes),
+            // Strings and slices arrive as bytes, the identity of those would be their last word
+            KeyHasher::Identity(_) => panic!("the identity hasher only takes integer keys"),
+            KeyHasher::Multiplicative(_) => {
+                for chunk in bytes.chunks(8) {
+                    let mut word = [0u8; 8];
+                    word[..chunk.len()].copy_from_slice(chunk);
+                    self.write_u64(u64::from_le_bytes(word));
+                }
+            }
+        }
+    }
+
+    // Integer keys of every width end up here, signed ones too
+    fn write_u64(&mut self, word: u64) {
+        match self {
+            KeyHasher::Sip(hasher) => hasher.write_u64(word),
+            KeyHasher::Multiplicative(state) => *state = (*state ^ word).wrapping_mul(FIBONACCI),
+            // A key of several words, a tuple or a length and its elements, is not an integer
+            KeyHasher::Identity(state) => {
+                assert!(state.replace(word).is_none(), "the identity hasher only takes integer keys");
+            }
+        }
+    }
+
+    fn write_u8(&mut self, word: u8) {
+        match self {
+            KeyHasher::Sip(hasher) => hasher.write_u8(word),
+            _ => self.write_u64(word.into()),
+        }
+    }
+
+    fn write_u16(&mut self, word: u16) {
+        match self {
+            KeyHasher::Sip(hasher) => hasher.write_u16(word),
+            _ => self.write_u64(word.into()),
+        }
+    }
+
+    fn write_u32(&mut self, word: u32) {
+        match self {
+            KeyHasher::Sip(hasher) => hasher.write_u32(word),
+            _ => self.write_u64(word.into()),
+        }
+    }
+
+    fn write_usize(&mut self, word: usize) {
+        match self {
+            KeyHasher::Sip(hasher) => hasher.write_usize(word),
+            _ => self.write_u64(word as u64),
+        }
+    }
+}
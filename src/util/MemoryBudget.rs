@@ -0,0 +1,111 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+// What a thread does when retiring would push the garbage of a tracker over its budget
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BudgetPolicy {
+    Block,    // Sleep until reclamation brings the garbage back under the cap, helping on every wakeup
+    SpinHelp, // Keep helping reclamation in a spin loop until it is back under the cap
+    Error,    // Give up right away and report the budget as exceeded
+}
+
+impl BudgetPolicy {
+    pub fn from_name(name: &str) -> Option<BudgetPolicy> {
+        match name {
+            "block" => Some(BudgetPolicy::Block),
+            "spin" => Some(BudgetPolicy::SpinHelp),
+            "error" => Some(BudgetPolicy::Error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BudgetExceeded {
+    pub unreclaimed_bytes: u64,
+    pub max_bytes: u64,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memory budget exceeded: {} of {} bytes unreclaimed", self.unreclaimed_bytes, self.max_bytes)
+    }
+}
+
+// Upper bound on the bytes a tracker may hold retired but not yet reclaimed.
+// The tracker charges every retired object and credits every reclaimed one.
+pub struct MemoryBudget {
+    max_bytes: u64,
+    policy: BudgetPolicy,
+    unreclaimed_bytes: AtomicU64,
+    waiters: AtomicUsize, // Threads sleeping in `wait`, reclaimers only notify if there are any
+    lock: Mutex<()>,
+    reclaimed: Condvar,
+}
+
+impl MemoryBudget {
+    pub fn new(max_bytes: u64, policy: BudgetPolicy) -> Self {
+        MemoryBudget {
+            max_bytes,
+            policy,
+            unreclaimed_bytes: AtomicU64::new(0),
+            waiters: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            reclaimed: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn charge(&self, bytes: u64) {
+        self.unreclaimed_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn credit(&self, bytes: u64) {
+        self.unreclaimed_bytes.fetch_sub(bytes, Ordering::Relaxed);
+        if self.waiters.load(Ordering::Acquire) != 0 {
+            let _guard = self.lock.lock().unwrap();
+            self.reclaimed.notify_all();
+        }
+    }
+
+    pub(crate) fn exceeded(&self) -> bool {
+        self.unreclaimed_bytes.load(Ordering::Relaxed) >= self.max_bytes
+    }
+
+    // Wait until the garbage is under the cap again, as the policy says. `help`
+    // is the forced reclamation of the tracker; it is run once before the policy
+    // kicks in and on every round of SpinHelp and Block. A sleeping thread helps
+    // too, as only its own scan can free the list of a scheme with per-thread lists.
+    pub(crate) fn wait<F: FnMut()>(&self, mut help: F) -> Result<(), BudgetExceeded> {
+        if !self.exceeded() {
+            return Ok(());
+        }
+        help();
+        while self.exceeded() {
+            match self.policy {
+                BudgetPolicy::Error => {
+                    return Err(BudgetExceeded {
+                        unreclaimed_bytes: self.unreclaimed_bytes.load(Ordering::Relaxed),
+                        max_bytes: self.max_bytes,
+                    });
+                }
+                BudgetPolicy::SpinHelp => {
+                    help();
+                    std::hint::spin_loop();
+                }
+                BudgetPolicy::Block => {
+                    self.waiters.fetch_add(1, Ordering::AcqRel);
+                    let guard = self.lock.lock().unwrap();
+                    // The timeout covers a credit that slipped in between the check and the wait
+                    if self.exceeded() {
+                        drop(self.reclaimed.wait_timeout(guard, Duration::from_millis(1)).unwrap());
+                    }
+                    self.waiters.fetch_sub(1, Ordering::AcqRel);
+                    help();
+                }
+            }
+        }
+        Ok(())
+    }
+}
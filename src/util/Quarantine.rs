@@ -0,0 +1,169 @@
+use std::alloc::Layout;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::thread;
+use crate::util::RAllocator::RAllocator;
+use crate::util::ThreadRegistry::ThreadToken;
+
+// Byte pattern freed memory is filled with while it sits in a quarantine
+pub(crate) const POISON: u8 = 0xDE;
+
+thread_local! {
+    // Map operation the calling thread runs in use-after-free mode, for the reports
+    static OPERATION: Cell<&'static str> = const { Cell::new("no map operation") };
+}
+
+// Names the map operation of the calling thread in use-after-free reports until
+// it is dropped, so that a write found when a block leaves the quarantine says
+// which operation freed it
+#[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+pub(crate) struct OperationScope(&'static str);
+
+#[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+impl OperationScope {
+    pub(crate) fn enter(op: &'static str) -> OperationScope {
+        OperationScope(OPERATION.replace(op))
+    }
+}
+
+#[cfg(any(feature = "unordered-map", feature = "split-ordered-map"))]
+impl Drop for OperationScope {
+    fn drop(&mut self) {
+        OPERATION.set(self.0);
+    }
+}
+
+// Report an access to freed memory and abort, a use-after-free must not go on
+// producing wrong results
+pub(crate) fn report_use_after_free(addr: *const u8, access: &str) -> ! {
+    let current = thread::current();
+    eprintln!("Use after free: {} at {:?} (thread {:?} {})", access, addr, current.id(), current.name().unwrap_or("unnamed"));
+    std::process::abort();
+}
+
+// Freed blocks are poisoned and held back for `capacity` further frees before they
+// really go away. A block whose poison changed while it was held was written after
+// it was freed; readers of freed memory see the poison and can check for it. Every
+// tracker holds its own quarantines, one per allocator.
+pub(crate) struct Quarantine {
+    ring: Mutex<VecDeque<(*mut u8, Layout)>>,
+    capacity: usize,
+}
+
+unsafe impl Send for Quarantine {}
+unsafe impl Sync for Quarantine {}
+
+impl Quarantine {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Quarantine { ring: Mutex::new(VecDeque::with_capacity(capacity + 1)), capacity }
+    }
+
+    // Poison a freed block and hold it, returns the oldest block once the ring is
+    // full, it is the caller's to release
+    pub(crate) fn hold(&self, ptr: *mut u8, layout: Layout) -> Option<(*mut u8, Layout)> {
+        unsafe { ptr.write_bytes(POISON, layout.size()) };
+        let mut ring = self.ring.lock().unwrap();
+        ring.push_back((ptr, layout));
+        if ring.len() <= self.capacity {
+            return None;
+        }
+        let (oldest, layout) = ring.pop_front().unwrap();
+        Quarantine::check(oldest, layout);
+        Some((oldest, layout))
+    }
+
+    // Every block still held, checked, for the owner to release
+    pub(crate) fn release_all(&mut self) -> Vec<(*mut u8, Layout)> {
+        let ring = self.ring.get_mut().unwrap();
+        for &(ptr, layout) in ring.iter() {
+            Quarantine::check(ptr, layout);
+        }
+        ring.drain(..).collect()
+    }
+
+    fn check(ptr: *mut u8, layout: Layout) {
+        let bytes = unsafe { std::slice::from_raw_parts(ptr, layout.size()) };
+        if let Some(offset) = bytes.iter().position(|&byte| byte != POISON) {
+            let access = format!("write to a freed block, found when it left the quarantine during {}", OPERATION.get());
+            report_use_after_free(unsafe { ptr.add(offset) }, &access);
+        }
+    }
+}
+
+// Puts every block freed through the inner allocator in a quarantine first
+pub(crate) struct QuarantineAllocator {
+    inner: Box<dyn RAllocator>,
+    layout: Layout,
+    quarantine: Quarantine,
+}
+
+impl QuarantineAllocator {
+    // `inner` itself when there is nothing to hold back
+    pub(crate) fn wrap(inner: Box<dyn RAllocator>, layout: Layout, capacity: usize) -> Box<dyn RAllocator> {
+        if capacity == 0 {
+            return inner;
+        }
+        Box::new(QuarantineAllocator { inner, layout, quarantine: Quarantine::new(capacity) })
+    }
+}
+
+impl RAllocator for QuarantineAllocator {
+    fn alloc_block(&self, token: &ThreadToken) -> *mut u8 {
+        self.inner.alloc_block(token)
+    }
+
+    fn free_block(&self, ptr: *mut u8, token: Option<&ThreadToken>) {
+        if let Some((oldest, _)) = self.quarantine.hold(ptr, self.layout) {
+            self.inner.free_block(oldest, token);
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("{} behind a quarantine of {} blocks", self.inner.describe(), self.quarantine.capacity)
+    }
+}
+
+impl Drop for QuarantineAllocator {
+    fn drop(&mut self) {
+        for (ptr, _) in self.quarantine.release_all() {
+            self.inner.free_block(ptr, None);
+        }
+    }
+}
+
+// Releases memory that came from the global allocator, e.g. boxed objects a
+// tracker did not allocate itself. In use-after-free mode it goes through a
+// quarantine of the tracker first.
+pub(crate) struct SystemRelease {
+    quarantine: Option<Quarantine>,
+}
+
+impl SystemRelease {
+    pub(crate) fn new(capacity: usize) -> Self {
+        SystemRelease { quarantine: (capacity > 0).then(|| Quarantine::new(capacity)) }
+    }
+
+    pub(crate) fn release(&self, ptr: *mut u8, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        let released = match &self.quarantine {
+            Some(quarantine) => quarantine.hold(ptr, layout),
+            None => Some((ptr, layout)),
+        };
+        if let Some((ptr, layout)) = released {
+            unsafe { std::alloc::dealloc(ptr, layout) };
+        }
+    }
+}
+
+impl Drop for SystemRelease {
+    fn drop(&mut self) {
+        if let Some(quarantine) = &mut self.quarantine {
+            for (ptr, layout) in quarantine.release_all() {
+                unsafe { std::alloc::dealloc(ptr, layout) };
+            }
+        }
+    }
+}
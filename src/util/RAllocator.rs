@@ -1,38 +1,380 @@
-// RAllocator trait extending Rideable
-trait RAllocator: Rideable {
-    // Allocates a block of memory. Returns a pointer (raw pointer in Rust)
-    // tid: Thread ID, unique across all threads
-    fn alloc_block(&self, tid: i32) -> *mut std::ffi::c_void;
+use std::alloc::{alloc, dealloc, Layout};
+use std::mem::{align_of, size_of};
+use std::cell::UnsafeCell;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use crate::util::ThreadRegistry::{ThreadRegistry, ThreadToken};
 
-    // Frees the block of memory
-    // tid: Thread ID, unique across all threads
-    fn free_block(&self, ptr: *mut std::ffi::c_void, tid: i32);
+// Blocks a thread keeps before half of them go to the shared depot
+const MAX_LOCAL_BLOCKS: usize = 256;
+// Blocks moved between a thread and the depot at once
+const DEPOT_BATCH: usize = MAX_LOCAL_BLOCKS / 2;
+// Bytes of address space the arena maps at once
+const ARENA_REGION_BYTES: usize = 256 << 20;
+// Blocks a thread takes from or gives back to the arena at once
+const ARENA_BATCH: usize = 32;
+// End of a slab's free list
+const NO_BLOCK: u32 = u32::MAX;
+
+// Allocator of fixed-size blocks, used by the trackers for the blocks that hold
+// the nodes of a data structure. Threads pass the token they registered with the
+// tracker; frees outside of a registered thread (e.g. a tracker being torn down)
+// pass None.
+pub(crate) trait RAllocator: Send + Sync {
+    // Allocates a block of the allocator's layout
+    fn alloc_block(&self, token: &ThreadToken) -> *mut u8;
+
+    // Frees a block handed out by `alloc_block`
+    fn free_block(&self, ptr: *mut u8, token: Option<&ThreadToken>);
+
+    // Name and counters, for the results
+    fn describe(&self) -> String;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AllocatorType {
+    System, // Every block comes from and goes back to the system allocator
+    Pool,   // Per-thread free lists, see PoolAllocator
+    Arena,  // mmap'd slabs that go back to the OS once empty, see ArenaAllocator
+}
+
+impl AllocatorType {
+    pub fn from_name(name: &str) -> Option<AllocatorType> {
+        match name {
+            "system" => Some(AllocatorType::System),
+            "pool" => Some(AllocatorType::Pool),
+            "arena" => Some(AllocatorType::Arena),
+            _ => None,
+        }
+    }
+
+    // An allocator of this type for blocks of `layout`
+    pub(crate) fn build(self, layout: Layout) -> Box<dyn RAllocator> {
+        match self {
+            AllocatorType::System => Box::new(SystemAllocator::new(layout)),
+            AllocatorType::Pool => Box::new(PoolAllocator::new(layout)),
+            AllocatorType::Arena => Box::new(ArenaAllocator::new(layout)),
+        }
+    }
+}
+
+fn system_alloc(layout: Layout) -> *mut u8 {
+    let ptr = unsafe { alloc(layout) };
+    if ptr.is_null() {
+        panic!("Memory allocation failed!");
+    }
+    ptr
+}
+
+pub(crate) struct SystemAllocator {
+    layout: Layout,
+}
+
+impl SystemAllocator {
+    pub(crate) fn new(layout: Layout) -> Self {
+        SystemAllocator { layout }
+    }
+}
+
+impl RAllocator for SystemAllocator {
+    fn alloc_block(&self, _token: &ThreadToken) -> *mut u8 {
+        system_alloc(self.layout)
+    }
+
+    fn free_block(&self, ptr: *mut u8, _token: Option<&ThreadToken>) {
+        unsafe { dealloc(ptr, self.layout) };
+    }
+
+    fn describe(&self) -> String {
+        "system".to_string()
+    }
+}
+
+// Free blocks of one thread, only touched by the owner
+#[derive(Default)]
+struct FreeList {
+    blocks: UnsafeCell<Vec<*mut u8>>,
+    fresh: AtomicU64,    // Blocks this thread took from the system allocator
+    recycled: AtomicU64, // Blocks this thread reused
 }
 
-// Example concrete type that implements RAllocator
-struct MemoryAllocator;
+unsafe impl Sync for FreeList {}
 
-impl Rideable for MemoryAllocator {
-    fn ride(&self) {
-        println!("Memory Allocator riding!");
+// Type-stable pool: a block is only ever reused as a block of the same layout and
+// goes back to the system when the allocator is dropped. Freed blocks are kept on
+// the freeing thread's list and reused by its next allocations. A thread that
+// frees more than it allocates hands batches to a shared depot, and a thread that
+// runs dry takes a batch from there before asking the system for memory.
+pub(crate) struct PoolAllocator {
+    layout: Layout,
+    threads: ThreadRegistry<FreeList>, // Indexed by the tracker's tokens
+    depot: Mutex<Vec<Vec<*mut u8>>>,   // Batches of free blocks any thread can take
+}
+
+unsafe impl Send for PoolAllocator {}
+unsafe impl Sync for PoolAllocator {}
+
+impl PoolAllocator {
+    pub(crate) fn new(layout: Layout) -> Self {
+        PoolAllocator { layout, threads: ThreadRegistry::new(), depot: Mutex::new(Vec::new()) }
     }
 }
 
-impl RAllocator for MemoryAllocator {
-    fn alloc_block(&self, tid: i32) -> *mut std::ffi::c_void {
-        println!("Allocating block for thread {}", tid);
-        // Simulate memory allocation, return a raw pointer (dummy pointer for illustration)
-        let block: *mut i32 = Box::into_raw(Box::new(0));
-        block as *mut std::ffi::c_void
+impl RAllocator for PoolAllocator {
+    fn alloc_block(&self, token: &ThreadToken) -> *mut u8 {
+        let local = self.threads.get_or_grow(token);
+        let blocks = unsafe { &mut *local.blocks.get() };
+        if blocks.is_empty() {
+            if let Some(batch) = self.depot.lock().unwrap().pop() {
+                *blocks = batch;
+            }
+        }
+        match blocks.pop() {
+            Some(ptr) => {
+                local.recycled.fetch_add(1, Ordering::Relaxed);
+                ptr
+            }
+            None => {
+                local.fresh.fetch_add(1, Ordering::Relaxed);
+                system_alloc(self.layout)
+            }
+        }
     }
 
-    fn free_block(&self, ptr: *mut std::ffi::c_void, tid: i32) {
-        println!("Freeing block for thread {}", tid);
-        // Convert the raw pointer back and free the allocated memory
-        if !ptr.is_null() {
-            unsafe {
-                Box::from_raw(ptr as *mut i32); // Deallocates the memory when the Box is dropped
+    fn free_block(&self, ptr: *mut u8, token: Option<&ThreadToken>) {
+        let Some(token) = token else {
+            let mut depot = self.depot.lock().unwrap();
+            match depot.last_mut() {
+                Some(batch) if batch.len() < DEPOT_BATCH => batch.push(ptr),
+                _ => depot.push(vec![ptr]),
             }
+            return;
+        };
+        let blocks = unsafe { &mut *self.threads.get_or_grow(token).blocks.get() };
+        blocks.push(ptr);
+        if blocks.len() > MAX_LOCAL_BLOCKS {
+            let batch = blocks.split_off(blocks.len() - DEPOT_BATCH);
+            self.depot.lock().unwrap().push(batch);
+        }
+    }
+
+    fn describe(&self) -> String {
+        let (mut fresh, mut recycled) = (0, 0);
+        self.threads.for_each(|_, local| {
+            fresh += local.fresh.load(Ordering::Relaxed);
+            recycled += local.recycled.load(Ordering::Relaxed);
+        });
+        format!("pool with {} fresh and {} recycled blocks", fresh, recycled)
+    }
+}
+
+impl Drop for PoolAllocator {
+    fn drop(&mut self) {
+        let layout = self.layout;
+        let release = |blocks: &mut Vec<*mut u8>| {
+            for ptr in blocks.drain(..) {
+                unsafe { dealloc(ptr, layout) };
+            }
+        };
+        self.threads.for_each(|_, local| release(unsafe { &mut *local.blocks.get() }));
+        for mut batch in self.depot.get_mut().unwrap().drain(..) {
+            release(&mut batch);
+        }
+    }
+}
+
+// Bookkeeping of one slab, kept outside of the mapped memory so a released
+// slab can be dropped from RSS entirely
+struct Slab {
+    live: u32,      // Blocks handed out, thread caches included
+    carved: u32,    // Blocks handed out at least once since the slab was last released
+    free_head: u32, // First freed block, the link to the next one is stored in the block
+    listed: bool,   // On the available stack
+}
+
+struct ArenaState {
+    regions: Vec<*mut u8>,
+    slabs: Vec<Slab>,
+    available: Vec<usize>, // Slabs with free blocks, allocations take from the top
+    resident: u64,  // Slabs in use since they were last released
+    released: u64,  // Times a slab was handed back to the OS
+}
+
+// Blocks a thread took from or freed to the arena in the current batch
+#[derive(Default)]
+struct ArenaCache {
+    allocs: UnsafeCell<Vec<*mut u8>>,
+    frees: UnsafeCell<Vec<*mut u8>>,
+}
+
+unsafe impl Sync for ArenaCache {}
+
+// Slab allocator on memory mapped straight from the OS. The address space is
+// mapped in regions and cut into slabs of whole pages, each holding blocks of a
+// single layout. Once every block of a slab is freed its pages are given back
+// with madvise(MADV_DONTNEED), so reclaimed nodes actually lower the RSS, which
+// the system allocator rarely does. Threads move blocks in batches to keep the
+// arena lock off the fast path.
+pub(crate) struct ArenaAllocator {
+    stride: usize,     // Block size, rounded up to the alignment
+    slab_bytes: usize, // Whole pages
+    slab_blocks: u32,
+    slabs_per_region: usize,
+    state: Mutex<ArenaState>,
+    threads: ThreadRegistry<ArenaCache>, // Indexed by the tracker's tokens
+}
+
+unsafe impl Send for ArenaAllocator {}
+unsafe impl Sync for ArenaAllocator {}
+
+impl ArenaAllocator {
+    pub(crate) fn new(layout: Layout) -> Self {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        // Freed blocks hold the index of the next free one
+        let stride = layout.size().max(size_of::<u32>()).next_multiple_of(layout.align().max(align_of::<u32>()));
+        assert!(layout.align() <= page, "Arena blocks cannot be aligned beyond a page");
+        let slab_bytes = stride.next_multiple_of(page);
+        ArenaAllocator {
+            stride,
+            slab_bytes,
+            slab_blocks: (slab_bytes / stride) as u32,
+            slabs_per_region: (ARENA_REGION_BYTES / slab_bytes).max(1),
+            state: Mutex::new(ArenaState {
+                regions: Vec::new(),
+                slabs: Vec::new(),
+                available: Vec::new(),
+                resident: 0,
+                released: 0,
+            }),
+            threads: ThreadRegistry::new(),
+        }
+    }
+
+    fn slab_start(&self, state: &ArenaState, slab: usize) -> *mut u8 {
+        let region = state.regions[slab / self.slabs_per_region];
+        unsafe { region.add(slab % self.slabs_per_region * self.slab_bytes) }
+    }
+
+    fn slab_of(&self, state: &ArenaState, ptr: *mut u8) -> usize {
+        let region_bytes = self.slabs_per_region * self.slab_bytes;
+        let index = state.regions.iter()
+            .position(|&region| ptr >= region && (ptr as usize) < region as usize + region_bytes)
+            .expect("Block was not allocated by this arena");
+        index * self.slabs_per_region + (ptr as usize - state.regions[index] as usize) / self.slab_bytes
+    }
+
+    // A slab with free blocks, mapping a new region when every slab is taken
+    fn available_slab(&self, state: &mut ArenaState) -> usize {
+        if let Some(&slab) = state.available.last() {
+            return slab;
+        }
+        if state.slabs.len() == state.regions.len() * self.slabs_per_region {
+            let region = unsafe {
+                libc::mmap(null_mut(), self.slabs_per_region * self.slab_bytes, libc::PROT_READ | libc::PROT_WRITE,
+                           libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE, -1, 0)
+            };
+            if region == libc::MAP_FAILED {
+                panic!("Memory allocation failed!");
+            }
+            state.regions.push(region as *mut u8);
+        }
+        state.slabs.push(Slab { live: 0, carved: 0, free_head: NO_BLOCK, listed: true });
+        state.available.push(state.slabs.len() - 1);
+        state.slabs.len() - 1
+    }
+
+    // Take up to `count` blocks from the available slabs
+    fn refill(&self, blocks: &mut Vec<*mut u8>, count: usize) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        while blocks.len() < count {
+            let index = self.available_slab(state);
+            let start = self.slab_start(state, index);
+            let slab = &mut state.slabs[index];
+            if slab.live == 0 && slab.carved == 0 {
+                state.resident += 1;
+            }
+            while blocks.len() < count && slab.live < self.slab_blocks {
+                let block = if slab.free_head != NO_BLOCK {
+                    let block = unsafe { start.add(slab.free_head as usize * self.stride) };
+                    slab.free_head = unsafe { *(block as *const u32) };
+                    block
+                } else {
+                    slab.carved += 1;
+                    unsafe { start.add((slab.carved - 1) as usize * self.stride) }
+                };
+                slab.live += 1;
+                blocks.push(block);
+            }
+            if slab.live == self.slab_blocks {
+                slab.listed = false;
+                state.available.pop();
+            }
+        }
+    }
+
+    // Give blocks back to their slabs, releasing the slabs that become empty
+    fn release(&self, blocks: &mut Vec<*mut u8>) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        for block in blocks.drain(..) {
+            let index = self.slab_of(state, block);
+            let start = self.slab_start(state, index);
+            let slab = &mut state.slabs[index];
+            slab.live -= 1;
+            if slab.live == 0 {
+                // Every block is free, the pages read as zero from now on
+                unsafe { libc::madvise(start as *mut libc::c_void, self.slab_bytes, libc::MADV_DONTNEED) };
+                slab.carved = 0;
+                slab.free_head = NO_BLOCK;
+                state.resident -= 1;
+                state.released += 1;
+            } else {
+                unsafe { *(block as *mut u32) = slab.free_head };
+                slab.free_head = ((block as usize - start as usize) / self.stride) as u32;
+            }
+            if !slab.listed {
+                slab.listed = true;
+                state.available.push(index);
+            }
+        }
+    }
+}
+
+impl RAllocator for ArenaAllocator {
+    fn alloc_block(&self, token: &ThreadToken) -> *mut u8 {
+        let blocks = unsafe { &mut *self.threads.get_or_grow(token).allocs.get() };
+        if blocks.is_empty() {
+            self.refill(blocks, ARENA_BATCH);
+        }
+        blocks.pop().unwrap()
+    }
+
+    fn free_block(&self, ptr: *mut u8, token: Option<&ThreadToken>) {
+        let Some(token) = token else {
+            self.release(&mut vec![ptr]);
+            return;
+        };
+        let blocks = unsafe { &mut *self.threads.get_or_grow(token).frees.get() };
+        blocks.push(ptr);
+        if blocks.len() >= ARENA_BATCH {
+            self.release(blocks);
+        }
+    }
+
+    fn describe(&self) -> String {
+        let state = self.state.lock().unwrap();
+        format!("arena with {} of {} slabs of {} bytes resident, {} released to the OS",
+                state.resident, state.slabs.len(), self.slab_bytes, state.released)
+    }
+}
+
+impl Drop for ArenaAllocator {
+    fn drop(&mut self) {
+        let region_bytes = self.slabs_per_region * self.slab_bytes;
+        for &region in &self.state.get_mut().unwrap().regions {
+            unsafe { libc::munmap(region as *mut libc::c_void, region_bytes) };
         }
     }
 }
@@ -1,31 +1,120 @@
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-
-struct GlobalTestConfig {
-    task_num: usize,
-}
-
-struct RetiredMonitorable {
-    retired_cnt: Vec<AtomicU64>, // A vector to hold AtomicU64 values for each thread
-}
-
-impl RetiredMonitorable {
-    fn new(gtc: &GlobalTestConfig) -> Self {
-        // Initialize the retired_cnt with AtomicU64 for each task_num
-        let retired_cnt = (0..gtc.task_num)
-            .map(|_| AtomicU64::new(0))
-            .collect::<Vec<AtomicU64>>();
-
-        RetiredMonitorable { retired_cnt }
-    }
-
-    // Collects the retired size for the given thread id
-    fn collect_retired_size(&self, size: u64, tid: usize) {
-        self.retired_cnt[tid].fetch_add(size, Ordering::SeqCst);
-    }
-
-    // Reports the retired size for the given thread id
-    fn report_retired(&self, tid: usize) -> u64 {
-        self.retired_cnt[tid].load(Ordering::SeqCst)
-    }
-}
\ No newline at end of file
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::fmt;
+
+// Reclamation counters of one thread. Allocations and retirements are counted by
+// the owning thread. A reclamation is counted on the thread that retired the
+// object, whichever thread actually frees it, so `retired - reclaimed` is the
+// garbage this thread is still waiting on.
+#[derive(Default)]
+pub(crate) struct RetiredCounters {
+    allocated: AtomicU64,
+    allocated_bytes: AtomicU64,
+    retired: AtomicU64,
+    retired_bytes: AtomicU64,
+    reclaimed: AtomicU64,
+    reclaimed_bytes: AtomicU64,
+    peak_unreclaimed: AtomicU64,
+    peak_unreclaimed_bytes: AtomicU64,
+}
+
+impl RetiredCounters {
+    // Called by the owning thread for every object it allocates
+    pub(crate) fn collect_alloc(&self, bytes: u64) {
+        self.allocated.fetch_add(1, Ordering::Relaxed);
+        self.allocated_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    // Called by the owning thread for every object it retires
+    pub(crate) fn collect_retired_size(&self, bytes: u64) {
+        let retired = self.retired.fetch_add(1, Ordering::Relaxed) + 1;
+        let retired_bytes = self.retired_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        // Garbage only grows here, so this is the only place the peak can move
+        let unreclaimed = retired.saturating_sub(self.reclaimed.load(Ordering::Relaxed));
+        let unreclaimed_bytes = retired_bytes.saturating_sub(self.reclaimed_bytes.load(Ordering::Relaxed));
+        self.peak_unreclaimed.fetch_max(unreclaimed, Ordering::Relaxed);
+        self.peak_unreclaimed_bytes.fetch_max(unreclaimed_bytes, Ordering::Relaxed);
+    }
+
+    // Called by any thread that frees objects retired by the owning thread
+    pub(crate) fn collect_reclaimed_size(&self, count: u64, bytes: u64) {
+        self.reclaimed.fetch_add(count, Ordering::Relaxed);
+        self.reclaimed_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn report_retired(&self) -> RetiredStats {
+        // Load reclaimed first so a concurrent reclamation never makes it exceed retired
+        let reclaimed = self.reclaimed.load(Ordering::Relaxed);
+        let reclaimed_bytes = self.reclaimed_bytes.load(Ordering::Relaxed);
+        RetiredStats {
+            allocated: self.allocated.load(Ordering::Relaxed),
+            allocated_bytes: self.allocated_bytes.load(Ordering::Relaxed),
+            retired: self.retired.load(Ordering::Relaxed),
+            retired_bytes: self.retired_bytes.load(Ordering::Relaxed),
+            reclaimed,
+            reclaimed_bytes,
+            peak_unreclaimed: self.peak_unreclaimed.load(Ordering::Relaxed),
+            peak_unreclaimed_bytes: self.peak_unreclaimed_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// A snapshot of the counters of one thread, or the sum over several threads
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RetiredStats {
+    pub allocated: u64,
+    pub allocated_bytes: u64,
+    pub retired: u64,
+    pub retired_bytes: u64,
+    pub reclaimed: u64,
+    pub reclaimed_bytes: u64,
+    pub peak_unreclaimed: u64,       // For a sum: the sum of the per-thread peaks
+    pub peak_unreclaimed_bytes: u64,
+}
+
+impl RetiredStats {
+    pub fn unreclaimed(&self) -> u64 {
+        self.retired.saturating_sub(self.reclaimed)
+    }
+
+    pub fn unreclaimed_bytes(&self) -> u64 {
+        self.retired_bytes.saturating_sub(self.reclaimed_bytes)
+    }
+
+    pub(crate) fn add(&mut self, other: &RetiredStats) {
+        self.allocated += other.allocated;
+        self.allocated_bytes += other.allocated_bytes;
+        self.retired += other.retired;
+        self.retired_bytes += other.retired_bytes;
+        self.reclaimed += other.reclaimed;
+        self.reclaimed_bytes += other.reclaimed_bytes;
+        self.peak_unreclaimed += other.peak_unreclaimed;
+        self.peak_unreclaimed_bytes += other.peak_unreclaimed_bytes;
+    }
+}
+
+impl fmt::Display for RetiredStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "allocated {} ({} B), retired {} ({} B), reclaimed {} ({} B), unreclaimed {} ({} B), peak unreclaimed {} ({} B)",
+               self.allocated, self.allocated_bytes,
+               self.retired, self.retired_bytes,
+               self.reclaimed, self.reclaimed_bytes,
+               self.unreclaimed(), self.unreclaimed_bytes(),
+               self.peak_unreclaimed, self.peak_unreclaimed_bytes)
+    }
+}
+
+// Implemented by every tracker. The counters can be queried at any time while
+// threads are running, e.g. by the memory monitor of the harness.
+pub trait RetiredMonitorable {
+    // Counters of every thread slot, indexed by slot
+    fn collect_retired_stats(&self) -> Vec<(usize, RetiredStats)>;
+
+    // Counters summed over all threads
+    fn report_retired(&self) -> RetiredStats {
+        let mut total = RetiredStats::default();
+        for (_, stats) in self.collect_retired_stats() {
+            total.add(&stats);
+        }
+        total
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt::Write;
+
+// A retired entry still linked in a retirement list
+pub(crate) struct RetiredEntryState {
+    pub(crate) addr: usize,
+    pub(crate) nref: i64,
+    pub(crate) batch: Vec<usize>, // Other members of the batch the entry heads
+}
+
+// One retirement list and the head it hangs off
+pub(crate) struct RetirementListState {
+    pub(crate) name: String,
+    pub(crate) href: i64,
+    pub(crate) seq: Option<u32>, // Only the shared 128-bit head counts insertions
+    pub(crate) hptr: usize,
+    pub(crate) entries: Vec<RetiredEntryState>, // From the head down, only those that cannot be freed yet
+    pub(crate) truncated: bool, // Older entries are still linked but may already be freed
+}
+
+// Retirement lists of a tracker and the batches threads have not inserted yet
+pub(crate) struct RetirementState {
+    pub(crate) lists: Vec<RetirementListState>,
+    pub(crate) pending: Vec<(usize, Vec<usize>)>, // Thread slot, entries of its local batch
+}
+
+pub(crate) struct NodeState {
+    pub(crate) addr: usize,
+    pub(crate) key: String,
+    pub(crate) value: String,
+    pub(crate) marked: bool, // Deleted, still waiting to be unlinked
+}
+
+// Snapshot of a data structure and its tracker, taken while no thread runs an
+// operation, rendered as Graphviz DOT or JSON
+pub struct StateSnapshot {
+    pub(crate) tracker: String,
+    pub(crate) retirement: Option<RetirementState>,
+    pub(crate) buckets: Vec<Vec<NodeState>>,
+}
+
+fn json_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(result, "\\u{:04x}", c as u32).unwrap(),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+// Text inside a DOT record label, where these characters are field syntax
+fn dot_label(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '"' | '\\' | '{' | '}' | '|' | '<' | '>') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn json_addrs(addrs: &[usize]) -> String {
+    let addrs: Vec<String> = addrs.iter().map(|addr| format!("\"{:#x}\"", addr)).collect();
+    format!("[{}]", addrs.join(", "))
+}
+
+impl StateSnapshot {
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "{{").unwrap();
+        writeln!(out, "  \"tracker\": {},", json_string(&self.tracker)).unwrap();
+        match &self.retirement {
+            None => writeln!(out, "  \"retirement\": null,").unwrap(),
+            Some(retirement) => {
+                writeln!(out, "  \"retirement\": {{").unwrap();
+                writeln!(out, "    \"lists\": [").unwrap();
+                for (i, list) in retirement.lists.iter().enumerate() {
+                    let seq = list.seq.map_or("null".to_string(), |seq| seq.to_string());
+                    writeln!(out, "      {{\"name\": {}, \"href\": {}, \"seq\": {}, \"hptr\": \"{:#x}\", \"truncated\": {}, \"entries\": [",
+                             json_string(&list.name), list.href, seq, list.hptr, list.truncated).unwrap();
+                    for (j, entry) in list.entries.iter().enumerate() {
+                        let comma = if j + 1 < list.entries.len() { "," } else { "" };
+                        writeln!(out, "        {{\"addr\": \"{:#x}\", \"nref\": {}, \"batch\": {}}}{}",
+                                 entry.addr, entry.nref, json_addrs(&entry.batch), comma).unwrap();
+                    }
+                    let comma = if i + 1 < retirement.lists.len() { "," } else { "" };
+                    writeln!(out, "      ]}}{}", comma).unwrap();
+                }
+                writeln!(out, "    ],").unwrap();
+                let pending: Vec<String> = retirement.pending.iter()
+                    .map(|(tid, batch)| format!("{{\"thread\": {}, \"batch\": {}}}", tid, json_addrs(batch)))
+                    .collect();
+                writeln!(out, "    \"pending\": [{}]", pending.join(", ")).unwrap();
+                writeln!(out, "  }},").unwrap();
+            }
+        }
+        writeln!(out, "  \"buckets\": [").unwrap();
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            let nodes: Vec<String> = bucket.iter()
+                .map(|node| format!("{{\"addr\": \"{:#x}\", \"key\": {}, \"value\": {}, \"marked\": {}}}",
+                                    node.addr, json_string(&node.key), json_string(&node.value), node.marked))
+                .collect();
+            let comma = if i + 1 < self.buckets.len() { "," } else { "" };
+            writeln!(out, "    [{}]{}", nodes.join(", "), comma).unwrap();
+        }
+        writeln!(out, "  ]").unwrap();
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph state {{").unwrap();
+        writeln!(out, "  rankdir=LR;").unwrap();
+        writeln!(out, "  node [shape=record, fontname=\"monospace\"];").unwrap();
+        writeln!(out, "  label=\"{}\";", dot_label(&self.tracker)).unwrap();
+
+        if let Some(retirement) = &self.retirement {
+            writeln!(out, "  subgraph cluster_retirement {{").unwrap();
+            writeln!(out, "    label=\"retirement\";").unwrap();
+            for (i, list) in retirement.lists.iter().enumerate() {
+                let seq = list.seq.map_or(String::new(), |seq| format!("|seq {}", seq));
+                writeln!(out, "    head{} [label=\"{{{}|href {}{}|hptr {:#x}}}\"];",
+                         i, dot_label(&list.name), list.href, seq, list.hptr).unwrap();
+                let mut previous = format!("head{}", i);
+                for entry in &list.entries {
+                    // An entry can be linked from several per-thread lists, one node per list
+                    let name = format!("r{}_{:x}", i, entry.addr);
+                    writeln!(out, "    {} [label=\"{{{:#x}|nref {}}}\"];", name, entry.addr, entry.nref).unwrap();
+                    writeln!(out, "    {} -> {};", previous, name).unwrap();
+                    for member in &entry.batch {
+                        writeln!(out, "    b_{:x} [label=\"{:#x}\", shape=box, style=dashed];", member, member).unwrap();
+                        writeln!(out, "    {} -> b_{:x} [style=dashed];", name, member).unwrap();
+                    }
+                    previous = name;
+                }
+                if list.truncated {
+                    writeln!(out, "    more{} [label=\"older entries, possibly freed\", shape=plaintext];", i).unwrap();
+                    writeln!(out, "    {} -> more{} [style=dotted];", previous, i).unwrap();
+                }
+            }
+            for (tid, batch) in &retirement.pending {
+                let members: Vec<String> = batch.iter().map(|addr| format!("{:#x}", addr)).collect();
+                writeln!(out, "    pending{} [label=\"{{pending batch of thread {}|{}}}\"];", tid, tid, members.join("|")).unwrap();
+            }
+            writeln!(out, "  }}").unwrap();
+        }
+
+        writeln!(out, "  subgraph cluster_buckets {{").unwrap();
+        writeln!(out, "    label=\"buckets\";").unwrap();
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            writeln!(out, "    bucket{} [label=\"bucket {}\", shape=box];", i, i).unwrap();
+            let mut previous = format!("bucket{}", i);
+            for node in bucket {
+                let name = format!("n_{:x}", node.addr);
+                let style = if node.marked { ", style=filled, fillcolor=lightgray" } else { "" };
+                writeln!(out, "    {} [label=\"{{{:#x}|{}|{}}}\"{}];",
+                         name, node.addr, dot_label(&node.key), dot_label(&node.value), style).unwrap();
+                writeln!(out, "    {} -> {};", previous, name).unwrap();
+                previous = name;
+            }
+        }
+        writeln!(out, "  }}").unwrap();
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
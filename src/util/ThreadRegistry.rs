@@ -0,0 +1,185 @@
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::ptr::null_mut;
+
+// Number of slots allocated at once when the registry grows
+const SLOTS_PER_CHUNK: usize = 64;
+// Id of the next registry, 0 stands for none
+static NEXT_REGISTRY_ID: AtomicUsize = AtomicUsize::new(1);
+
+// Per-thread state of one registered thread
+struct Slot<S> {
+    in_use: AtomicBool,
+    state: S,
+}
+
+// Slots are allocated in chunks that are never moved or freed while the registry
+// lives, so a slot can be reached without locks while other threads register.
+struct Chunk<S> {
+    slots: Vec<Slot<S>>,
+    next: AtomicPtr<Chunk<S>>,
+}
+
+impl<S: Default> Chunk<S> {
+    fn new() -> *mut Chunk<S> {
+        let slots = (0..SLOTS_PER_CHUNK)
+            .map(|_| Slot { in_use: AtomicBool::new(false), state: S::default() })
+            .collect();
+        Box::into_raw(Box::new(Chunk { slots, next: AtomicPtr::new(null_mut()) }))
+    }
+}
+
+// Returned by `register`, identifies the slot of a thread until `unregister`.
+// The token names the registry that issued it and is only accepted there. It
+// can move to another thread but not be shared, two threads using one slot
+// at the same time would corrupt its state.
+pub struct ThreadToken {
+    index: usize,
+    registry: usize,
+    _not_sync: PhantomData<*const ()>,
+}
+
+unsafe impl Send for ThreadToken {}
+
+impl ThreadToken {
+    // Slot of the thread, for diagnostics
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+// Hands out per-thread slots at runtime instead of a fixed tid range. Slots of
+// unregistered threads are recycled, new chunks are added when all slots are taken.
+pub(crate) struct ThreadRegistry<S> {
+    head: *mut Chunk<S>,
+    registered: AtomicUsize, // Slots currently claimed
+    id: usize,
+    issuer: AtomicUsize, // Registry whose tokens index this one through `get_or_grow`, bound on first use
+}
+
+unsafe impl<S: Send + Sync> Send for ThreadRegistry<S> {}
+unsafe impl<S: Send + Sync> Sync for ThreadRegistry<S> {}
+
+impl<S: Default> ThreadRegistry<S> {
+    pub(crate) fn new() -> Self {
+        ThreadRegistry {
+            head: Chunk::new(),
+            registered: AtomicUsize::new(0),
+            id: NEXT_REGISTRY_ID.fetch_add(1, Ordering::Relaxed),
+            issuer: AtomicUsize::new(0),
+        }
+    }
+
+    // Claim a free slot, growing the registry if every slot is taken
+    pub(crate) fn register(&self) -> ThreadToken {
+        let mut chunk = self.head;
+        let mut base = 0;
+        loop {
+            let current = unsafe { &*chunk };
+            for (i, slot) in current.slots.iter().enumerate() {
+                if !slot.in_use.load(Ordering::Relaxed)
+                    && slot.in_use.compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+                    self.registered.fetch_add(1, Ordering::Relaxed);
+                    return ThreadToken { index: base + i, registry: self.id, _not_sync: PhantomData };
+                }
+            }
+            chunk = ThreadRegistry::next_or_grow(current);
+            base += SLOTS_PER_CHUNK;
+        }
+    }
+
+    // State kept for a token handed out by another registry, for tables that sit
+    // next to a tracker's own registry. The registry grows as far as the token needs.
+    // Tokens of a second registry are refused.
+    pub(crate) fn get_or_grow(&self, token: &ThreadToken) -> &S {
+        let mut issuer = self.issuer.load(Ordering::Relaxed);
+        if issuer == 0 {
+            issuer = match self.issuer.compare_exchange(0, token.registry, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => token.registry,
+                Err(actual) => actual,
+            };
+        }
+        assert_eq!(issuer, token.registry, "Thread token used with a tracker that did not issue it");
+        let mut chunk = self.head;
+        for _ in 0..token.index / SLOTS_PER_CHUNK {
+            chunk = ThreadRegistry::next_or_grow(unsafe { &*chunk });
+        }
+        let chunk = unsafe { &*chunk };
+        &chunk.slots[token.index % SLOTS_PER_CHUNK].state
+    }
+
+    fn next_or_grow(current: &Chunk<S>) -> *mut Chunk<S> {
+        let next = current.next.load(Ordering::Acquire);
+        if !next.is_null() {
+            return next;
+        }
+        let new_chunk = Chunk::new();
+        match current.next.compare_exchange(null_mut(), new_chunk, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => new_chunk,
+            Err(actual) => {
+                // Another thread grew the registry first
+                unsafe { drop(Box::from_raw(new_chunk)) };
+                actual
+            }
+        }
+    }
+}
+
+impl<S> ThreadRegistry<S> {
+    // Panics unless this registry issued `token`
+    pub(crate) fn check(&self, token: &ThreadToken) {
+        assert_eq!(token.registry, self.id, "Thread token used with a tracker that did not issue it");
+    }
+
+    fn slot(&self, token: &ThreadToken) -> &Slot<S> {
+        self.check(token);
+        let mut chunk = self.head;
+        for _ in 0..token.index / SLOTS_PER_CHUNK {
+            chunk = unsafe { (*chunk).next.load(Ordering::Acquire) };
+            assert!(!chunk.is_null(), "Thread token {} beyond the slots of its registry", token.index);
+        }
+        let chunk = unsafe { &*chunk };
+        &chunk.slots[token.index % SLOTS_PER_CHUNK]
+    }
+
+    // The state of the thread that owns `token`
+    pub(crate) fn get(&self, token: &ThreadToken) -> &S {
+        &self.slot(token).state
+    }
+
+    // Give the slot back. The state is left as is for the next owner of the slot.
+    pub(crate) fn unregister(&self, token: ThreadToken) {
+        self.slot(&token).in_use.store(false, Ordering::Release);
+        self.registered.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // Number of threads currently registered
+    #[cfg(any(feature = "interval", feature = "hyaline"))]
+    pub(crate) fn registered(&self) -> usize {
+        self.registered.load(Ordering::Relaxed)
+    }
+
+    // Visit the state of every slot, registered or not
+    pub(crate) fn for_each<F: FnMut(usize, &S)>(&self, mut f: F) {
+        let mut chunk = self.head;
+        let mut base = 0;
+        while !chunk.is_null() {
+            let current = unsafe { &*chunk };
+            for (i, slot) in current.slots.iter().enumerate() {
+                f(base + i, &slot.state);
+            }
+            chunk = current.next.load(Ordering::Acquire);
+            base += SLOTS_PER_CHUNK;
+        }
+    }
+}
+
+impl<S> Drop for ThreadRegistry<S> {
+    fn drop(&mut self) {
+        let mut chunk = self.head;
+        while !chunk.is_null() {
+            let current = unsafe { Box::from_raw(chunk) };
+            chunk = current.next.load(Ordering::Acquire);
+        }
+    }
+}